@@ -0,0 +1,171 @@
+//! https://man7.org/linux/man-pages/man7/capabilities.7.html
+
+use crate::utils::libc_call;
+
+use std::io;
+
+/// capability names indexed by their numbers
+const CAP_NAMES: &[&str] = &[
+    "chown",
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "fsetid",
+    "kill",
+    "setgid",
+    "setuid",
+    "setpcap",
+    "linux_immutable",
+    "net_bind_service",
+    "net_broadcast",
+    "net_admin",
+    "net_raw",
+    "ipc_lock",
+    "ipc_owner",
+    "sys_module",
+    "sys_rawio",
+    "sys_chroot",
+    "sys_ptrace",
+    "sys_pacct",
+    "sys_admin",
+    "sys_boot",
+    "sys_nice",
+    "sys_resource",
+    "sys_time",
+    "sys_tty_config",
+    "mknod",
+    "lease",
+    "audit_write",
+    "audit_control",
+    "setfcap",
+    "mac_override",
+    "mac_admin",
+    "syslog",
+    "wake_alarm",
+    "block_suspend",
+    "audit_read",
+    "perfmon",
+    "bpf",
+    "checkpoint_restore",
+];
+
+/// A set of capabilities
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CapSet(u64);
+
+impl CapSet {
+    /// parse capability names like `CAP_NET_RAW` or `net_raw`
+    pub fn from_names(names: &[String]) -> Result<Self, String> {
+        let mut bits = 0;
+        for name in names {
+            let lower = name.to_ascii_lowercase();
+            let short = lower.strip_prefix("cap_").unwrap_or(&lower);
+            match CAP_NAMES.iter().position(|&n| n == short) {
+                Some(cap) => bits |= 1 << cap,
+                None => return Err(format!("unknown capability: {}", name)),
+            }
+        }
+        Ok(Self(bits))
+    }
+
+    pub fn contains(self, cap: u32) -> bool {
+        cap < 64 && self.0 & (1 << cap) != 0
+    }
+
+    fn caps(self) -> impl Iterator<Item = u32> {
+        (0..64).filter(move |&cap| self.contains(cap))
+    }
+}
+
+fn prctl(option: libc::c_int, arg2: libc::c_ulong) -> io::Result<u32> {
+    libc_call(|| unsafe { libc::prctl(option, arg2, 0, 0, 0) })
+}
+
+pub fn set_no_new_privs() -> io::Result<()> {
+    prctl(libc::PR_SET_NO_NEW_PRIVS, 1)?;
+    Ok(())
+}
+
+/// keep the permitted capabilities when switching from root to a non-root uid
+pub fn set_keep_caps() -> io::Result<()> {
+    prctl(libc::PR_SET_KEEPCAPS, 1)?;
+    Ok(())
+}
+
+/// drop all capabilities except `keep` from the bounding set
+pub fn drop_bounding_set(keep: CapSet) -> io::Result<()> {
+    for cap in 0..64 {
+        // `PR_CAPBSET_READ` fails with EINVAL after the last capability
+        if prctl(libc::PR_CAPBSET_READ, cap.into()).is_err() {
+            break;
+        }
+        if !keep.contains(cap) {
+            prctl(libc::PR_CAPBSET_DROP, cap.into())?;
+        }
+    }
+    Ok(())
+}
+
+/// restrict the effective, permitted and inheritable sets to `keep`,
+/// and replace the ambient set with `keep` if `ambient` is true.
+///
+/// The ambient set keeps the capabilities of a non-root user across execve.
+pub fn restrict(keep: CapSet, ambient: bool) -> io::Result<()> {
+    #[repr(C)]
+    struct CapUserHeader {
+        version: u32,
+        pid: libc::c_int,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct CapUserData {
+        effective: u32,
+        permitted: u32,
+        inheritable: u32,
+    }
+
+    const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+    let mut header = CapUserHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data = [CapUserData {
+        effective: 0,
+        permitted: 0,
+        inheritable: 0,
+    }; 2];
+
+    unsafe {
+        let header: *mut CapUserHeader = &mut header;
+        let data: *mut CapUserData = data.as_mut_ptr();
+        libc_call(|| libc::syscall(libc::SYS_capget, header, data) as _)?;
+    }
+
+    for (i, d) in data.iter_mut().enumerate() {
+        let keep = (keep.0 >> (32 * i)) as u32;
+        d.permitted &= keep;
+        d.effective = d.permitted;
+        d.inheritable = d.permitted;
+    }
+
+    unsafe {
+        let header: *mut CapUserHeader = &mut header;
+        let data: *const CapUserData = data.as_ptr();
+        libc_call(|| libc::syscall(libc::SYS_capset, header, data) as _)?;
+    }
+
+    let clear_all: libc::c_ulong = libc::PR_CAP_AMBIENT_CLEAR_ALL as _;
+    libc_call(|| unsafe { libc::prctl(libc::PR_CAP_AMBIENT, clear_all, 0, 0, 0) })?;
+
+    if ambient {
+        let raise: libc::c_ulong = libc::PR_CAP_AMBIENT_RAISE as _;
+        for cap in keep.caps() {
+            let cap: libc::c_ulong = cap.into();
+            libc_call(|| unsafe { libc::prctl(libc::PR_CAP_AMBIENT, raise, cap, 0, 0) })?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+//! A pool of idle cgroups which are reused by later sandboxes in this process
+
+use crate::cgroup_v1::{Cgroup, Controllers};
+
+use std::sync::Mutex;
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use tracing::{trace, warn};
+
+struct Pool {
+    capacity: usize,
+    idle: Vec<Cgroup>,
+}
+
+static POOL: Lazy<Mutex<Pool>> = Lazy::new(|| {
+    Mutex::new(Pool {
+        capacity: 0,
+        idle: Vec::new(),
+    })
+});
+
+/// keep up to `capacity` idle cgroups, and remove the others
+pub fn set_capacity(capacity: usize) {
+    let removed = {
+        let mut pool = POOL.lock().unwrap();
+        pool.capacity = capacity;
+        let len = pool.idle.len().min(capacity);
+        pool.idle.split_off(len)
+    };
+    for cg in removed {
+        cg.remove();
+    }
+}
+
+/// take an idle cgroup with the same controllers, or create a new one named `name`
+pub fn acquire(name: &str, controllers: Controllers) -> Result<Cgroup> {
+    let idle = {
+        let mut pool = POOL.lock().unwrap();
+        let pos = pool
+            .idle
+            .iter()
+            .rposition(|cg| cg.controllers() == controllers);
+        pos.map(|pos| pool.idle.remove(pos))
+    };
+    match idle {
+        Some(cg) => {
+            trace!(path = ?cg.cpu(), "reuse cgroup");
+            Ok(cg)
+        }
+        None => Cgroup::create(name, controllers),
+    }
+}
+
+/// put the cgroup back into the pool if it can be reset, otherwise remove it
+pub fn release(cg: Cgroup, reusable: bool) {
+    let has_room = || {
+        let pool = POOL.lock().unwrap();
+        pool.idle.len() < pool.capacity
+    };
+
+    if reusable && has_room() {
+        match cg.reset() {
+            Ok(()) => {
+                let mut pool = POOL.lock().unwrap();
+                if pool.idle.len() < pool.capacity {
+                    pool.idle.push(cg);
+                    return;
+                }
+            }
+            Err(err) => warn!(%err, "failed to reset cgroup"),
+        }
+    }
+
+    cg.remove();
+}
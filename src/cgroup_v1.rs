@@ -1,29 +1,71 @@
 use std::fs::File;
 use std::io::Write as _;
+use std::path::Path;
 use std::str::FromStr;
 use std::{fmt, fs, io};
 
 use anyhow::{Context, Result};
-use nix::sys::stat::Mode;
+use nix::sys::stat::{self, Mode};
 use nix::unistd::{self, AccessFlags};
-use tracing::trace;
+use tracing::{trace, warn};
 
+#[derive(Clone)]
 pub struct Cgroup {
     cpu: String,
     memory: String,
     pids: String,
+    cpuset: Option<String>,
+    blkio: Option<String>,
+    devices: Option<String>,
+    freezer: Option<String>,
 }
 
+/// the optional controllers, which are only created and joined when their features are requested
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Controllers {
+    pub cpuset: bool,
+    pub blkio: bool,
+    pub devices: bool,
+    pub freezer: bool,
+}
+
+pub const BLKIO_THROTTLE_FILES: &[&str] = &[
+    "blkio.throttle.read_bps_device",
+    "blkio.throttle.write_bps_device",
+    "blkio.throttle.read_iops_device",
+    "blkio.throttle.write_iops_device",
+];
+
+/// the root of the cpuset hierarchy, from which unset `cpus` and `mems` are inherited
+pub const CPUSET_ROOT: &str = "/sys/fs/cgroup/cpuset";
+
 impl Cgroup {
-    pub fn create(name: &str) -> Result<Self> {
-        trace!(?name, "create cgroup");
+    pub fn create(name: &str, controllers: Controllers) -> Result<Self> {
+        trace!(?name, ?controllers, "create cgroup");
         let cpu = format!("/sys/fs/cgroup/cpu/{}", name);
         let memory = format!("/sys/fs/cgroup/memory/{}", name);
         let pids = format!("/sys/fs/cgroup/pids/{}", name);
         Self::ensure_dir(&cpu)?;
         Self::ensure_dir(&memory)?;
         Self::ensure_dir(&pids)?;
-        Ok(Self { cpu, memory, pids })
+
+        let optional = |enabled: bool, root: &str| -> Result<Option<String>> {
+            if !enabled {
+                return Ok(None);
+            }
+            let dir = format!("{}/{}", root, name);
+            Self::ensure_dir(&dir)?;
+            Ok(Some(dir))
+        };
+        Ok(Self {
+            cpu,
+            memory,
+            pids,
+            cpuset: optional(controllers.cpuset, CPUSET_ROOT)?,
+            blkio: optional(controllers.blkio, "/sys/fs/cgroup/blkio")?,
+            devices: optional(controllers.devices, "/sys/fs/cgroup/devices")?,
+            freezer: optional(controllers.freezer, "/sys/fs/cgroup/freezer")?,
+        })
     }
 
     pub fn cpu(&self) -> &str {
@@ -38,6 +80,102 @@ impl Cgroup {
         &self.pids
     }
 
+    pub fn cpuset(&self) -> Option<&str> {
+        self.cpuset.as_deref()
+    }
+
+    pub fn blkio(&self) -> Option<&str> {
+        self.blkio.as_deref()
+    }
+
+    pub fn devices(&self) -> Option<&str> {
+        self.devices.as_deref()
+    }
+
+    pub fn freezer(&self) -> Option<&str> {
+        self.freezer.as_deref()
+    }
+
+    pub fn controllers(&self) -> Controllers {
+        Controllers {
+            cpuset: self.cpuset.is_some(),
+            blkio: self.blkio.is_some(),
+            devices: self.devices.is_some(),
+            freezer: self.freezer.is_some(),
+        }
+    }
+
+    fn dirs(&self) -> Vec<&str> {
+        let mut dirs = vec![self.cpu.as_str(), &self.memory, &self.pids];
+        let optional = [&self.cpuset, &self.blkio, &self.devices, &self.freezer];
+        dirs.extend(optional.iter().filter_map(|dir| dir.as_deref()));
+        dirs
+    }
+
+    /// restore the default limits and clear the counters, so that the cgroup can be reused.
+    /// It fails if any process is left in the cgroup.
+    pub fn reset(&self) -> Result<()> {
+        for dir in self.dirs().iter() {
+            if !Self::read_string(dir, "cgroup.procs")?.is_empty() {
+                anyhow::bail!("cgroup is not empty: path = {}", dir);
+            }
+        }
+
+        // the memory+swap limit must not be less than the memory limit
+        if Self::has_file(&self.memory, "memory.memsw.limit_in_bytes") {
+            Self::write_type(&self.memory, "memory.memsw.limit_in_bytes", -1)?;
+        }
+        Self::write_type(&self.memory, "memory.limit_in_bytes", -1)?;
+        Self::write_type(&self.pids, "pids.max", "max")?;
+
+        if let Some(ref blkio) = self.blkio {
+            for file in BLKIO_THROTTLE_FILES {
+                // a rule is deleted by setting its limit to 0
+                for line in Self::read_string(blkio, file)?.lines() {
+                    if let Some((dev, _)) = line.split_once(' ') {
+                        Self::write_type(blkio, file, format!("{} 0", dev))?;
+                    }
+                }
+            }
+            Self::write_type(blkio, "blkio.reset_stats", 0)?;
+        }
+
+        if let Some(ref devices) = self.devices {
+            Self::write_type(devices, "devices.allow", "a")?;
+        }
+
+        if let Some(ref cpuset) = self.cpuset {
+            for file in &["cpuset.cpus", "cpuset.mems"] {
+                let value = Self::read_string(CPUSET_ROOT, file)?;
+                Self::write_type(cpuset, file, value.trim_end())?;
+            }
+        }
+
+        if let Some(ref freezer) = self.freezer {
+            Self::write_type(freezer, "freezer.state", "THAWED")?;
+        }
+
+        // uncharge the page cache which is left by the last sandbox
+        Self::write_type(&self.memory, "memory.force_empty", 0)?;
+
+        Self::write_type(&self.cpu, "cpuacct.usage", 0)?;
+        Self::write_type(&self.memory, "memory.max_usage_in_bytes", 0)?;
+        if Self::has_file(&self.memory, "memory.memsw.max_usage_in_bytes") {
+            Self::write_type(&self.memory, "memory.memsw.max_usage_in_bytes", 0)?;
+        }
+
+        Ok(())
+    }
+
+    /// remove all directories of the cgroup
+    pub fn remove(self) {
+        for dir in self.dirs().iter() {
+            if let Err(err) = Self::remove_dir(dir) {
+                warn!(path = ?dir, %err, "failed to remove cgroup dir")
+            }
+        }
+    }
+
     pub fn ensure_dir(cg_dir: &str) -> Result<()> {
         if unistd::access(cg_dir, AccessFlags::F_OK).is_ok() {
             return Ok(());
@@ -49,6 +187,12 @@ impl Cgroup {
         Ok(())
     }
 
+    /// whether `file` exists, since some controller files depend on the kernel configuration
+    pub fn has_file(cg_dir: &str, file: &str) -> bool {
+        let path = format!("{}/{}", cg_dir, file);
+        unistd::access(path.as_str(), AccessFlags::F_OK).is_ok()
+    }
+
     pub fn remove_dir(cg_dir: &str) -> io::Result<()> {
         fs::remove_dir(cg_dir)
     }
@@ -63,7 +207,8 @@ impl Cgroup {
     pub fn write_type(cg_dir: &str, file: &str, content: impl fmt::Display) -> io::Result<()> {
         let path = format!("{}/{}", cg_dir, file);
         let mut file = File::create(&path)?;
-        write!(file, "{}", content)?;
+        // cgroup files expect the whole content in a single write
+        file.write_all(content.to_string().as_bytes())?;
         Ok(())
     }
 
@@ -83,3 +228,42 @@ impl Cgroup {
         Ok(content)
     }
 }
+
+/// parse a list like `0-3,8,10-11`, which is the format of `cpuset.cpus` and `cpuset.mems`
+pub fn parse_list(s: &str) -> Option<Vec<usize>> {
+    let mut list = Vec::new();
+    for item in s.trim_end().split(',').filter(|item| !item.is_empty()) {
+        match item.split_once('-') {
+            Some((start, end)) => {
+                let start: usize = start.parse().ok()?;
+                let end: usize = end.parse().ok()?;
+                if start > end {
+                    return None;
+                }
+                list.extend(start..=end);
+            }
+            None => list.push(item.parse().ok()?),
+        }
+    }
+    Some(list)
+}
+
+/// the whole disk which backs `path`, as `major:minor`,
+/// or `None` if `path` is not on a block device, for example, on a tmpfs.
+///
+/// The throttling rules of blkio only apply to whole disks instead of partitions.
+pub fn whole_disk_of(path: &Path) -> Result<Option<String>> {
+    let st = stat::stat(path)?;
+    let (major, minor) = (stat::major(st.st_dev), stat::minor(st.st_dev));
+    if major == 0 {
+        return Ok(None);
+    }
+
+    let sys_dir = fs::canonicalize(format!("/sys/dev/block/{}:{}", major, minor))?;
+    let disk_dir = match sys_dir.parent() {
+        Some(parent) if sys_dir.join("partition").exists() => parent,
+        _ => &sys_dir,
+    };
+    let dev = fs::read_to_string(disk_dir.join("dev"))?;
+    Ok(Some(dev.trim_end().to_owned()))
+}
@@ -1,52 +1,112 @@
-use crate::cgroup_v1::Cgroup;
-use crate::mount::{bind_mount, make_root_private, mount_proc, mount_tmpfs};
+use crate::capability::{self, CapSet};
+use crate::cgroup_v1::{self, Cgroup};
+use crate::mount::{
+    bind_mount, make_root_private, mount_dev, mount_proc, mount_tmpfs, pivot_root, MountRoot,
+    DEVPTS_DEVICES, DEV_NODES,
+};
+use crate::net::bring_up_loopback;
+use crate::pipe::PipeTx;
+use crate::proc::{set_parent_death_signal, unshare_namespace};
 use crate::seccomp;
 use crate::utils::{self, RawFd};
-use crate::SandboxConfig;
+use crate::{DeviceRule, Namespace, RLimitResource, SandboxConfig, SchedPolicy};
 
 use std::borrow::Cow;
-use std::convert::{Infallible, TryInto};
-use std::ffi::{CString, OsString};
+use std::convert::Infallible;
+use std::ffi::{CString, OsStr, OsString};
 use std::io::Write;
 use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::{Path, PathBuf};
-use std::{env, fs, io, ptr};
+use std::{env, fs, io, iter, ptr};
 
 use anyhow::{Context, Result};
-use nix::fcntl::{self, OFlag};
-use nix::sys::stat::Mode;
-use nix::unistd::{self, AccessFlags, Gid, Uid};
+use nix::fcntl::{self, FcntlArg, FdFlag, OFlag};
+use nix::sched::{self, CpuSet};
+use nix::sys::stat::{self, Mode};
+use nix::unistd::{self, AccessFlags, Gid, Pid, Uid};
 use path_absolutize::Absolutize;
 use rlimit::Resource;
+use scopeguard::guard;
+use tracing::trace;
 
-pub fn run_child(config: &SandboxConfig, cgroup: &Cgroup) -> Result<Infallible> {
+pub fn run_child(config: &SandboxConfig, cgroup: &Cgroup, pipe_tx: &PipeTx) -> Result<Infallible> {
     unsafe { path_absolutize::update_cwd() };
 
+    let namespaces = config.namespaces();
+    for &ns in &[Namespace::Uts, Namespace::Ipc, Namespace::Net] {
+        if namespaces.contains(&ns) {
+            unshare_namespace(ns)?;
+        }
+    }
+
+    if namespaces.contains(&Namespace::Uts) {
+        set_uts_names(config)?;
+    }
+
+    if config.net_loopback {
+        bring_up_loopback().context("failed to bring up loopback interface")?;
+    }
+
+    let host_bin = if config.host_bin {
+        let fd = open_host_bin(config)
+            .with_context(|| format!("failed to open host bin: path = {}", config.bin.display()))?;
+        Some(fd)
+    } else {
+        None
+    };
+
     do_mount(config)?;
 
-    let exec = prepare_execve_args(config)?;
+    let mut exec = prepare_execve_args(config)?;
+
+    let extra_fds = stage_extra_fds(config).context("failed to stage extra fds")?;
 
     cg_setup_child(config, cgroup).context("failed to setup cgroup")?;
 
-    let reset: _ = cg_prepare_reset_metrics(cgroup).context("failed to prepare cgroup metrics")?;
+    if namespaces.contains(&Namespace::Cgroup) {
+        unshare_namespace(Namespace::Cgroup)?;
+    }
+
+    if let Some(adj) = config.oom_score_adj {
+        // `/proc` may be unavailable after chroot
+        fs::write("/proc/self/oom_score_adj", adj.to_string())
+            .context("failed to set oom_score_adj")?;
+    }
+
+    let reset = cg_prepare_reset_metrics(cgroup).context("failed to prepare cgroup metrics")?;
 
     if let Some(ref new_root) = config.chroot {
-        unistd::chroot(new_root)
-            .and_then(|_| unistd::chdir("/"))
-            .context("failed to chroot")?;
+        if config.pivot_root {
+            pivot_root(new_root).context("failed to pivot root")?;
+        } else {
+            unistd::chroot(new_root)
+                .and_then(|_| unistd::chdir("/"))
+                .context("failed to chroot")?;
+        }
     }
 
-    set_hard_rlimit(config)?;
+    if let Some(ref cwd) = config.cwd {
+        change_cwd(config, cwd)
+            .with_context(|| format!("failed to change cwd: cwd = {}", cwd.display()))?;
+    }
 
     if let Some(prio) = config.priority {
         utils::libc_call(|| unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, prio as _) })
             .context("failed to set priority")?;
     }
 
+    if let Some(policy) = config.sched_policy {
+        set_sched_policy(policy).context("failed to set scheduling policy")?;
+    }
+
     redirect_stdio(config)?;
 
-    unistd::access(&config.bin, AccessFlags::F_OK)
-        .with_context(|| format!("failed to access file: path = {}", config.bin.display()))?;
+    // NOTE:
+    // A small NOFILE limit would make the stdio files unable to open.
+    // The extra fds are checked against it by `validate`, since they are passed later.
+    set_rlimits(config)?;
+
+    capability::set_no_new_privs().context("failed to set no_new_privs")?;
 
     if config.seccomp_forbid_ipc {
         let mut seccomp_ctx = seccomp::Context::new();
@@ -56,14 +116,85 @@ pub fn run_child(config: &SandboxConfig, cgroup: &Cgroup) -> Result<Infallible>
 
     reset().context("failed to reset cgroup metrics")?;
 
+    let keep_caps = CapSet::from_names(&config.cap_keep).map_err(anyhow::Error::msg)?;
+    capability::drop_bounding_set(keep_caps).context("failed to drop bounding set")?;
+    if config.uid.is_some() {
+        capability::set_keep_caps().context("failed to set keepcaps")?;
+    }
+
     set_id(config)?;
 
-    unsafe { libc::execve(exec.bin, exec.args.as_ptr(), exec.env.as_ptr()) };
+    capability::restrict(keep_caps, config.uid.is_some())
+        .context("failed to restrict capabilities")?;
+
+    // NOTE:
+    // bin is checked with the credentials of the sandbox,
+    // so that a file which only root can execute is reported as not executable.
+    if config.host_bin {
+        // bin has been opened before chroot
+    } else if config.search_path && !config.bin.as_os_str().as_bytes().contains(&b'/') {
+        let bin = search_path(config)?;
+        trace!(?bin, "resolved bin in PATH");
+        exec.set_bin(CString::new(bin.into_os_string().into_vec())?);
+    } else {
+        unistd::access(&config.bin, AccessFlags::F_OK)
+            .with_context(|| format!("failed to access file: path = {}", config.bin.display()))?;
+    }
+
+    pass_fds(extra_fds).context("failed to pass fds")?;
+
+    // NOTE:
+    // The parent death signal is cleared by `setfsuid` and `setresuid`,
+    // so it is set again just before execve, with the same race check as after clone.
+    set_parent_death_signal(libc::SIGKILL).context("failed to set parent death signal")?;
+    if pipe_tx.is_peer_closed().unwrap_or(true) {
+        anyhow::bail!("parent has exited");
+    }
+
+    match host_bin {
+        Some(fd) => unsafe {
+            let empty = b"\0".as_ptr().cast::<libc::c_char>();
+            let flags = libc::AT_EMPTY_PATH;
+            let (args, env) = (exec.args.as_ptr(), exec.env.as_ptr());
+            libc::syscall(libc::SYS_execveat, fd, empty, args, env, flags)
+        },
+        None => unsafe { libc::execve(exec.bin, exec.args.as_ptr(), exec.env.as_ptr()) as _ },
+    };
 
     Err(io::Error::last_os_error())
         .with_context(|| format!("failed to execve: bin = {:?}", config.bin))
 }
 
+/// relative paths of bin and stdio are resolved against the cwd
+fn change_cwd(config: &SandboxConfig, cwd: &Path) -> Result<()> {
+    if unistd::access(cwd, AccessFlags::F_OK).is_err() {
+        fs::create_dir_all(cwd)?;
+        // the new directory is owned by the sandbox user
+        let uid = config.uid.map(Uid::from_raw);
+        let gid = config.gid.map(Gid::from_raw);
+        unistd::chown(cwd, uid, gid)?;
+    }
+    unistd::chdir(cwd)?;
+    Ok(())
+}
+
+const DEFAULT_HOSTNAME: &str = "carapace";
+const DEFAULT_DOMAINNAME: &str = "(none)";
+
+fn set_uts_names(config: &SandboxConfig) -> Result<()> {
+    let hostname = config.hostname.as_deref().unwrap_or(DEFAULT_HOSTNAME);
+    unistd::sethostname(hostname)
+        .with_context(|| format!("failed to set hostname: hostname = {:?}", hostname))?;
+
+    let domainname = config.domainname.as_deref().unwrap_or(DEFAULT_DOMAINNAME);
+    utils::libc_call(|| unsafe {
+        libc::setdomainname(domainname.as_ptr().cast(), domainname.len())
+    })
+    .with_context(|| format!("failed to set domainname: domainname = {:?}", domainname))?;
+
+    Ok(())
+}
+
 fn redirect_stdio(config: &SandboxConfig) -> Result<()> {
     fn redirect(file_fd: RawFd, stdio: RawFd) -> nix::Result<()> {
         let ret = unistd::dup2(file_fd, stdio);
@@ -72,45 +203,161 @@ fn redirect_stdio(config: &SandboxConfig) -> Result<()> {
         Ok(())
     }
 
-    fn get_file_fd(
-        path: &Option<PathBuf>,
-        fd: Option<RawFd>,
-        is_input: bool,
-    ) -> nix::Result<Option<RawFd>> {
-        if let Some(p) = path {
-            if is_input {
-                fcntl::open(p, OFlag::O_RDONLY | OFlag::O_CLOEXEC, Mode::empty())
+    let output_flags = if config.stdio_append {
+        OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_APPEND | OFlag::O_CLOEXEC
+    } else {
+        OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_TRUNC | OFlag::O_CLOEXEC
+    };
+    let output_mode = Mode::from_bits_truncate(config.stdio_mode.unwrap_or(0o644));
+
+    // the configured mode is applied exactly, instead of being masked by the inherited umask
+    let prev_umask = config.stdio_mode.map(|_| stat::umask(Mode::empty()));
+    let _restore_umask = guard((), |_| {
+        if let Some(mask) = prev_umask {
+            stat::umask(mask);
+        }
+    });
+
+    let get_file_fd =
+        |path: &Option<PathBuf>, fd: Option<RawFd>, is_input: bool| -> nix::Result<Option<RawFd>> {
+            if let Some(p) = path {
+                if is_input {
+                    fcntl::open(p, OFlag::O_RDONLY | OFlag::O_CLOEXEC, Mode::empty())
+                } else {
+                    fcntl::open(p, output_flags, output_mode)
+                }
+                .map(Some)
+            } else if let Some(f) = fd {
+                // checked by the parent
+                Ok(Some(f))
             } else {
-                fcntl::open(
-                    p,
-                    OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_TRUNC | OFlag::O_CLOEXEC,
-                    Mode::from_bits_truncate(0o644),
-                )
+                Ok(None)
             }
-            .map(Some)
-        } else if let Some(f) = fd {
-            // TODO: check mode
-            Ok(Some(f))
-        } else {
-            Ok(None)
-        }
+        };
+
+    // open the files with the credentials of the sandbox user,
+    // so that permissions are checked against the user and new files are owned by the user
+    if let Some(gid) = config.gid.map(Gid::from_raw) {
+        setgroups(&[gid]).context("failed to set groups")?;
     }
+    let prev_fsgid = config.gid.map(|gid| setfsgid(Gid::from_raw(gid)));
+    let prev_fsuid = config.uid.map(|uid| setfsuid(Uid::from_raw(uid)));
+    let _restore = guard((), |_| {
+        if let Some(uid) = prev_fsuid {
+            setfsuid(uid);
+        }
+        if let Some(gid) = prev_fsgid {
+            setfsgid(gid);
+        }
+    });
 
-    if let Some(fd) = get_file_fd(&config.stdin, config.stdin_fd, true)? {
+    let stdin_fd =
+        get_file_fd(&config.stdin, config.stdin_fd, true).context("failed to open stdin")?;
+    if let Some(fd) = stdin_fd {
         redirect(fd, libc::STDIN_FILENO).context("failed to redirect stdin")?;
     }
 
-    if let Some(fd) = get_file_fd(&config.stdout, config.stdout_fd, false)? {
+    let stdout_fd =
+        get_file_fd(&config.stdout, config.stdout_fd, false).context("failed to open stdout")?;
+    if let Some(fd) = stdout_fd {
         redirect(fd, libc::STDOUT_FILENO).context("failed to redirect stdout")?;
     }
 
-    if let Some(fd) = get_file_fd(&config.stderr, config.stderr_fd, false)? {
+    let stderr_fd =
+        get_file_fd(&config.stderr, config.stderr_fd, false).context("failed to open stderr")?;
+    if let Some(fd) = stderr_fd {
         redirect(fd, libc::STDERR_FILENO).context("failed to redirect stderr")?;
     }
 
     Ok(())
 }
 
+struct ExtraFds {
+    fds: Vec<(RawFd, RawFd)>, // (child fd, staged fd)
+}
+
+/// the fds below this number are reserved for stdio and the extra fds
+pub fn reserved_fd_end(config: &SandboxConfig) -> RawFd {
+    let end = config.extra_fds.iter().map(|&(c, _)| c + 1).max();
+    end.unwrap_or(0).max(3)
+}
+
+/// move the extra fds out of the way before stdio redirection,
+/// and mark the other inherited fds as close-on-exec
+fn stage_extra_fds(config: &SandboxConfig) -> Result<ExtraFds> {
+    set_cloexec_above_stderr()?;
+
+    let min_fd = reserved_fd_end(config);
+
+    let mut fds = Vec::with_capacity(config.extra_fds.len());
+    for &(child_fd, parent_fd) in &config.extra_fds {
+        let staged_fd = fcntl::fcntl(parent_fd, FcntlArg::F_DUPFD_CLOEXEC(min_fd))
+            .with_context(|| format!("failed to dup fd: fd = {}", parent_fd))?;
+        fds.push((child_fd, staged_fd));
+    }
+
+    Ok(ExtraFds { fds })
+}
+
+/// mark every fd above stderr as close-on-exec
+///
+/// It is done before chroot, because the fallback needs `/proc`.
+/// The fds which are opened afterwards are close-on-exec by themselves.
+fn set_cloexec_above_stderr() -> Result<()> {
+    // NOTE:
+    // The fds are marked as close-on-exec instead of being closed immediately,
+    // so that the pipe to the parent is still usable until execve.
+    let ret = utils::libc_call(|| unsafe {
+        let flags: libc::c_long = CLOSE_RANGE_CLOEXEC as _;
+        libc::syscall(libc::SYS_close_range, 3, libc::c_uint::MAX, flags) as _
+    });
+    if ret.is_err() {
+        // `CLOSE_RANGE_CLOEXEC` requires Linux 5.11.
+        // Only the open fds are visited, since the NOFILE limit can be huge.
+        let fds: Vec<RawFd> = fs::read_dir("/proc/self/fd")
+            .context("failed to list open fds")?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect();
+        for fd in fds.into_iter().filter(|&fd| fd > libc::STDERR_FILENO) {
+            let _ = fcntl::fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC));
+        }
+    }
+    Ok(())
+}
+
+/// open bin on the host, out of the reserved range
+///
+/// The fd is close-on-exec, so it is not leaked into the sandbox.
+/// As a consequence, scripts can not be executed by fd, because the interpreter
+/// would have to open the script through `/dev/fd`.
+fn open_host_bin(config: &SandboxConfig) -> Result<RawFd> {
+    let fd = fcntl::open(
+        &config.bin,
+        OFlag::O_RDONLY | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    )?;
+    let fd = guard(fd, |fd| {
+        let _ = unistd::close(fd);
+    });
+    let moved_fd = fcntl::fcntl(*fd, FcntlArg::F_DUPFD_CLOEXEC(reserved_fd_end(config)))?;
+    Ok(moved_fd)
+}
+
+/// move the staged extra fds to their places, which are kept open on execve
+///
+/// The fds which are still in use must not be in the reserved range.
+fn pass_fds(extra_fds: ExtraFds) -> Result<()> {
+    for (child_fd, staged_fd) in extra_fds.fds {
+        let ret = unistd::dup2(staged_fd, child_fd);
+        let _ = unistd::close(staged_fd);
+        ret.with_context(|| format!("failed to pass fd: fd = {}", child_fd))?;
+    }
+
+    Ok(())
+}
+
+const CLOSE_RANGE_CLOEXEC: libc::c_uint = 1 << 2;
+
 fn do_mount(config: &SandboxConfig) -> Result<()> {
     make_root_private()?;
 
@@ -120,20 +367,22 @@ fn do_mount(config: &SandboxConfig) -> Result<()> {
         Cow::Borrowed("/".as_ref())
     };
 
-    let get_real_dst = |dst: &Path| -> Result<OsString> {
-        let dst = dst.absolutize_virtually("/")?;
-        let mut real_dst: OsString = root.as_os_str().into();
-        real_dst.push(dst.as_os_str());
-        Ok(real_dst)
-    };
+    let root = MountRoot::open(&root)
+        .with_context(|| format!("failed to open root: path = {}", root.display()))?;
+
+    // mounted first, so that its tmpfs does not hide the other mounts under `/dev`
+    if config.mount_dev {
+        let dst: &Path = "/dev".as_ref();
+        mount_dev(&root, dst, config.mount_devpts, config.mount_devshm)
+            .with_context(|| format!("failed to mount dev: dst = {}", dst.display()))?;
+    }
 
-    let rw_mnts: _ = config.bindmount_rw.iter().map(|m| (m, false));
-    let ro_mnts: _ = config.bindmount_ro.iter().map(|m| (m, true));
+    let rw_mnts = config.bindmount_rw.iter().map(|m| (m, false));
+    let ro_mnts = config.bindmount_ro.iter().map(|m| (m, true));
 
     for (mnt, readonly) in rw_mnts.chain(ro_mnts) {
-        let real_dst = get_real_dst(&mnt.dst)?;
         let src: &Path = &mnt.src;
-        let dst: &Path = real_dst.as_ref();
+        let dst: &Path = &mnt.dst;
         let on_err = || {
             format!(
                 "failed to do bind mount: src = {}, dst = {}, readonly = {}",
@@ -142,41 +391,40 @@ fn do_mount(config: &SandboxConfig) -> Result<()> {
                 readonly
             )
         };
-        bind_mount(src, dst, true, readonly).with_context(on_err)?;
+        bind_mount(src, &root, dst, true, readonly).with_context(on_err)?;
     }
 
-    if let Some(ref mnt) = config.mount_proc {
-        let real_dst = get_real_dst(mnt)?;
-        let dst: &Path = real_dst.as_ref();
-        mount_proc(dst)
+    if let Some(ref dst) = config.mount_proc {
+        mount_proc(&root, dst)
             .with_context(|| format!("failed to mount proc: dst = {}", dst.display()))?;
     }
 
-    if let Some(ref mnt) = config.mount_tmpfs {
-        let real_dst = get_real_dst(mnt)?;
-        let dst: &Path = real_dst.as_ref();
-        mount_tmpfs(dst)
+    if let Some(ref dst) = config.mount_tmpfs {
+        mount_tmpfs(&root, dst)
             .with_context(|| format!("failed to mount tmpfs: dst = {}", dst.display()))?;
     }
 
     Ok(())
 }
 
-fn set_hard_rlimit(config: &SandboxConfig) -> Result<()> {
-    macro_rules! direct_set {
-        ($res:expr, $field:ident) => {
-            if let Some($field) = config.$field {
-                let $field: u64 = $field.try_into()?;
-                $res.set($field, $field)?;
-            }
+fn set_rlimits(config: &SandboxConfig) -> Result<()> {
+    for rlimit in &config.rlimits {
+        let resource = match rlimit.resource {
+            RLimitResource::As => Resource::AS,
+            RLimitResource::Core => Resource::CORE,
+            RLimitResource::Cpu => Resource::CPU,
+            RLimitResource::Data => Resource::DATA,
+            RLimitResource::Fsize => Resource::FSIZE,
+            RLimitResource::Memlock => Resource::MEMLOCK,
+            RLimitResource::Msgqueue => Resource::MSGQUEUE,
+            RLimitResource::Nofile => Resource::NOFILE,
+            RLimitResource::Nproc => Resource::NPROC,
+            RLimitResource::Stack => Resource::STACK,
         };
+        resource
+            .set(rlimit.soft, rlimit.hard)
+            .with_context(|| format!("failed to set rlimit: rlimit = {:?}", rlimit))?;
     }
-
-    direct_set!(Resource::CPU, rlimit_cpu);
-    direct_set!(Resource::AS, rlimit_as);
-    direct_set!(Resource::DATA, rlimit_data);
-    direct_set!(Resource::FSIZE, rlimit_fsize);
-
     Ok(())
 }
 
@@ -231,6 +479,77 @@ fn prepare_execve_args(config: &SandboxConfig) -> Result<ExecveArgs> {
     })
 }
 
+impl ExecveArgs {
+    /// replace the path to execute, while `argv[0]` stays as given
+    fn set_bin(&mut self, bin: CString) {
+        self.bin = bin.as_ptr();
+        self._cstrings.push(bin);
+    }
+}
+
+/// the value of `name` in the sandbox environment
+fn env_var(config: &SandboxConfig, name: &str) -> Option<OsString> {
+    let name = name.as_bytes();
+    config.env.iter().rev().find_map(|e| {
+        let e = e.as_bytes();
+        match e.iter().position(|&b| b == b'=') {
+            Some(pos) if &e[..pos] == name => Some(OsStr::from_bytes(&e[pos + 1..]).to_owned()),
+            Some(_) => None,
+            None if e == name => env::var_os(OsStr::from_bytes(e)),
+            None => None,
+        }
+    })
+}
+
+/// search bin in the directories of PATH, like `execvpe`
+fn search_path(config: &SandboxConfig) -> Result<PathBuf> {
+    // the default search path of glibc `execvpe`
+    const DEFAULT_PATH: &str = "/bin:/usr/bin";
+
+    let path_var = env_var(config, "PATH").unwrap_or_else(|| DEFAULT_PATH.into());
+
+    let mut not_executable = None;
+    for dir in path_var.as_bytes().split(|&b| b == b':') {
+        // an empty entry means the cwd
+        let dir = if dir.is_empty() { b"." } else { dir };
+        let candidate = Path::new(OsStr::from_bytes(dir)).join(&config.bin);
+
+        match fs::metadata(&candidate) {
+            Ok(meta) if meta.is_file() => {
+                if unistd::access(&candidate, AccessFlags::X_OK).is_ok() {
+                    return Ok(candidate);
+                }
+                not_executable.get_or_insert(candidate);
+            }
+            _ => continue,
+        }
+    }
+
+    if let Some(candidate) = not_executable {
+        anyhow::bail!("bin is not executable: path = {}", candidate.display());
+    }
+    anyhow::bail!(
+        "bin is not found in PATH: bin = {}, PATH = {:?}",
+        config.bin.display(),
+        path_var
+    )
+}
+
+fn set_sched_policy(policy: SchedPolicy) -> io::Result<()> {
+    let policy = match policy {
+        SchedPolicy::Other => libc::SCHED_OTHER,
+        SchedPolicy::Batch => libc::SCHED_BATCH,
+        SchedPolicy::Idle => libc::SCHED_IDLE,
+    };
+    // the static priority of non-realtime policies must be 0
+    let param = libc::sched_param { sched_priority: 0 };
+    utils::libc_call(|| unsafe { libc::sched_setscheduler(0, policy, &param) })?;
+    Ok(())
+}
+
+/// the default length of a CFS period, in microseconds
+const DEFAULT_CPU_PERIOD: u64 = 100_000;
+
 fn cg_setup_child(config: &SandboxConfig, cg: &Cgroup) -> Result<()> {
     Cgroup::add_self_proc(cg.cpu()).context("failed to add self to cpu cgroup")?;
     Cgroup::add_self_proc(cg.memory()).context("failed to add self to memory cgroup")?;
@@ -238,6 +557,19 @@ fn cg_setup_child(config: &SandboxConfig, cg: &Cgroup) -> Result<()> {
     if let Some(memory_limit) = config.cg_limit_memory {
         Cgroup::write_type(cg.memory(), "memory.limit_in_bytes", memory_limit)
             .context("failed to set memory limit")?;
+
+        // NOTE: the memsw files are missing when swap accounting is disabled
+        match config.cg_limit_memsw {
+            Some(memsw_limit) => {
+                Cgroup::write_type(cg.memory(), "memory.memsw.limit_in_bytes", memsw_limit)
+                    .context("failed to set memory+swap limit")?;
+            }
+            None if Cgroup::has_file(cg.memory(), "memory.memsw.limit_in_bytes") => {
+                Cgroup::write_type(cg.memory(), "memory.memsw.limit_in_bytes", memory_limit)
+                    .context("failed to disable swap")?;
+            }
+            None => {}
+        }
     }
 
     if let Some(pids_max) = config.cg_limit_max_pids {
@@ -246,16 +578,131 @@ fn cg_setup_child(config: &SandboxConfig, cg: &Cgroup) -> Result<()> {
         Cgroup::add_self_proc(cg.pids()).context("failed to add self to pids cgroup")?;
     }
 
+    if let Some(cpu_quota) = config.cpu_quota {
+        let cpu_period = config.cpu_period.unwrap_or(DEFAULT_CPU_PERIOD);
+        Cgroup::write_type(cg.cpu(), "cpu.cfs_period_us", cpu_period)
+            .context("failed to set cpu period")?;
+        Cgroup::write_type(cg.cpu(), "cpu.cfs_quota_us", cpu_quota)
+            .context("failed to set cpu quota")?;
+    }
+
+    let io_limits = [
+        ("blkio.throttle.read_bps_device", config.io_read_bps),
+        ("blkio.throttle.write_bps_device", config.io_write_bps),
+        ("blkio.throttle.read_iops_device", config.io_read_iops),
+        ("blkio.throttle.write_iops_device", config.io_write_iops),
+    ];
+    // the blkio controller is only created with io limits
+    if let Some(blkio) = cg.blkio() {
+        // the sandbox can write to the disks of chroot and the rw bind mounts
+        let workspace = config.chroot.as_deref().unwrap_or_else(|| Path::new("/"));
+        let rw_srcs = config.bindmount_rw.iter().map(|mnt| mnt.src.as_path());
+        let mut disks: Vec<String> = Vec::new();
+        for path in iter::once(workspace).chain(rw_srcs) {
+            let disk = cgroup_v1::whole_disk_of(path)
+                .with_context(|| format!("failed to find disk: path = {}", path.display()))?;
+            if let Some(disk) = disk {
+                if !disks.contains(&disk) {
+                    disks.push(disk);
+                }
+            }
+        }
+        if disks.is_empty() {
+            anyhow::bail!(
+                "no block device to throttle: workspace = {}",
+                workspace.display()
+            );
+        }
+        trace!(?disks, "throttle disks");
+
+        for &(file, limit) in &io_limits {
+            if let Some(limit) = limit {
+                for disk in &disks {
+                    Cgroup::write_type(blkio, file, format!("{} {}", disk, limit))
+                        .with_context(|| format!("failed to set {}: disk = {}", file, disk))?;
+                }
+            }
+        }
+        Cgroup::add_self_proc(blkio).context("failed to add self to blkio cgroup")?;
+    }
+
+    // the freezer controller is only created for the sandboxes which can be paused
+    if let Some(freezer) = cg.freezer() {
+        Cgroup::add_self_proc(freezer).context("failed to add self to freezer cgroup")?;
+    }
+
+    // the devices controller is only created with `restrict_devices`
+    if let Some(devices) = cg.devices() {
+        Cgroup::write_type(devices, "devices.deny", "a").context("failed to deny devices")?;
+        // the nodes of `mount_dev` are always allowed, and the ptys only with `mount_devpts`
+        let char_dev = |major, minor| DeviceRule {
+            kind: 'c',
+            major: Some(major),
+            minor,
+            access: "rw".to_owned(),
+        };
+        let mut rules: Vec<DeviceRule> = DEV_NODES
+            .iter()
+            .map(|&(_, major, minor)| char_dev(major, Some(minor)))
+            .collect();
+        if config.mount_devpts {
+            rules.extend(
+                DEVPTS_DEVICES
+                    .iter()
+                    .map(|&(major, minor)| char_dev(major, minor)),
+            );
+        }
+        rules.extend(config.device_allow.iter().cloned());
+        for rule in &rules {
+            Cgroup::write_type(devices, "devices.allow", rule)
+                .with_context(|| format!("failed to allow device: rule = {}", rule))?;
+        }
+        Cgroup::add_self_proc(devices).context("failed to add self to devices cgroup")?;
+    }
+
+    // the cpuset controller is only created with `cpus` or `mems`
+    if let Some(cpuset) = cg.cpuset() {
+        // both of them must be set before a process can join the cpuset
+        for (file, value) in &[("cpuset.cpus", &config.cpus), ("cpuset.mems", &config.mems)] {
+            let value = match value {
+                Some(value) => value.clone(),
+                None => Cgroup::read_string(cgroup_v1::CPUSET_ROOT, file)?,
+            };
+            Cgroup::write_type(cpuset, file, value.trim_end())
+                .with_context(|| format!("failed to set {}", file))?;
+        }
+        Cgroup::add_self_proc(cpuset).context("failed to add self to cpuset cgroup")?;
+    }
+
+    if let Some(ref cpus) = config.cpus {
+        // the cpuset restricts the whole process tree,
+        // while the affinity pins the current thread immediately
+        let mut cpu_set = CpuSet::new();
+        for cpu in cgroup_v1::parse_list(cpus).unwrap_or_default() {
+            cpu_set.set(cpu)?;
+        }
+        sched::sched_setaffinity(Pid::from_raw(0), &cpu_set).context("failed to set affinity")?;
+    }
+
     Ok(())
 }
 
 fn cg_prepare_reset_metrics(cg: &Cgroup) -> Result<impl FnOnce() -> Result<()>> {
-    let mut cpu = fs::File::create(&format!("{}/cpuacct.usage", cg.cpu()))?;
-    let mut mem = fs::File::create(&format!("{}/memory.max_usage_in_bytes", cg.memory()))?;
+    let mut cpu = fs::File::create(format!("{}/cpuacct.usage", cg.cpu()))?;
+    let mut mem = fs::File::create(format!("{}/memory.max_usage_in_bytes", cg.memory()))?;
+    let mut memsw = if Cgroup::has_file(cg.memory(), "memory.memsw.max_usage_in_bytes") {
+        let path = format!("{}/memory.memsw.max_usage_in_bytes", cg.memory());
+        Some(fs::File::create(path)?)
+    } else {
+        None
+    };
 
     Ok(move || {
         write!(cpu, "0")?;
         write!(mem, "0")?;
+        if let Some(ref mut memsw) = memsw {
+            write!(memsw, "0")?;
+        }
         Ok(())
     })
 }
@@ -321,3 +768,21 @@ fn setresuid(ruid: Uid, euid: Uid, suid: Uid) -> io::Result<()> {
         Ok(())
     }
 }
+
+/// returns the previous fsuid
+fn setfsuid(fsuid: Uid) -> Uid {
+    unsafe {
+        let fsuid: libc::c_long = fsuid.as_raw() as _;
+        let ret = libc::syscall(libc::SYS_setfsuid, fsuid);
+        Uid::from_raw(ret as _)
+    }
+}
+
+/// returns the previous fsgid
+fn setfsgid(fsgid: Gid) -> Gid {
+    unsafe {
+        let fsgid: libc::c_long = fsgid.as_raw() as _;
+        let ret = libc::syscall(libc::SYS_setfsgid, fsgid);
+        Gid::from_raw(ret as _)
+    }
+}
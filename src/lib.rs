@@ -3,24 +3,29 @@
 #[macro_use]
 mod utils;
 
+mod capability;
+mod cgroup_pool;
 mod cgroup_v1;
 mod child;
 mod cmd;
 mod mount;
+mod net;
 mod pipe;
 mod proc;
 mod run;
+mod sample;
 mod seccomp;
 mod signal;
 
 pub use crate::cmd::Command;
+pub use crate::run::Sandbox;
 
 use crate::utils::RawFd;
 
 use std::ffi::{OsStr, OsString};
 use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
-use std::process;
+use std::{fmt, process};
 
 use anyhow::Result;
 use clap::Clap;
@@ -31,7 +36,33 @@ pub fn run(config: &SandboxConfig) -> Result<SandboxOutput> {
     crate::run::run(config)
 }
 
-#[derive(Debug, Default, Serialize, Deserialize, Clap)]
+/// Starts a sandbox without waiting for it, so that it can be paused and resumed.
+///
+/// The sandbox must be waited on the calling thread, since it is killed when the thread exits.
+pub fn spawn(config: &SandboxConfig) -> Result<Sandbox> {
+    crate::run::spawn(config)
+}
+
+/// Kills all sandboxes which are running in this process.
+///
+/// The sandboxes which are spawned afterwards are killed as soon as they start,
+/// so it is meant for shutting down, for example, on `SIGINT`.
+/// The interrupted `run` calls still wait for their children and clean up their cgroups.
+pub fn kill_all() {
+    crate::signal::kill_all()
+}
+
+/// Keeps up to `size` idle cgroups for the later sandboxes in this process,
+/// which saves the cost of creating and removing cgroups for each run.
+///
+/// The pool is disabled by default. Setting the size to 0 removes all idle cgroups.
+/// Callers must set it to 0 before exiting, otherwise the idle `carapace_*` cgroups
+/// are left on the host.
+pub fn set_cgroup_pool_size(size: usize) {
+    crate::cgroup_pool::set_capacity(size)
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, Clap)]
 #[clap(
     version = clap::crate_version!(),
     author = clap::crate_authors!(),
@@ -45,9 +76,38 @@ pub struct SandboxConfig {
     #[clap(short = 'e', long)]
     pub env: Vec<OsString>,
 
+    #[clap(long)]
+    pub search_path: bool, // search bin in the PATH of `env` inside the chroot, like `execvpe`
+
+    #[clap(long, conflicts_with = "search-path")]
+    pub host_bin: bool, // open bin on the host before chroot and execute it by fd
+
+    #[clap(
+        long,
+        value_name = "ns",
+        use_delimiter = true,
+        parse(try_from_str = Namespace::try_from_str)
+    )]
+    pub namespaces: Option<Vec<Namespace>>, // defaults to uts, pid, net. The mount namespace is always created.
+
+    #[clap(long, value_name = "name")]
+    pub hostname: Option<String>, // defaults to "carapace" in the UTS namespace
+
+    #[clap(long, value_name = "name")]
+    pub domainname: Option<String>, // defaults to "(none)" in the UTS namespace
+
+    #[clap(long)]
+    pub net_loopback: bool, // bring up `lo` in the network namespace
+
     #[clap(short = 'c', long, value_name = "path")]
     pub chroot: Option<PathBuf>, // relative to cwd
 
+    #[clap(long, requires = "chroot")]
+    pub pivot_root: bool, // use pivot_root instead of chroot
+
+    #[clap(long, value_name = "path")]
+    pub cwd: Option<PathBuf>, // absolute (affected by chroot), created if missing
+
     #[clap(long)]
     pub uid: Option<u32>,
 
@@ -63,6 +123,12 @@ pub struct SandboxConfig {
     #[clap(long, value_name = "path")]
     pub stderr: Option<PathBuf>, // relative to chroot
 
+    #[clap(long, value_name = "mode", parse(try_from_str = parse_mode))]
+    pub stdio_mode: Option<u32>, // octal, the permission bits of created output files
+
+    #[clap(long)]
+    pub stdio_append: bool, // append to output files instead of truncating them
+
     #[clap(long, value_name = "fd", conflicts_with = "stdin")]
     pub stdin_fd: Option<RawFd>,
 
@@ -72,26 +138,65 @@ pub struct SandboxConfig {
     #[clap(long, value_name = "fd", conflicts_with = "stderr")]
     pub stderr_fd: Option<RawFd>,
 
+    #[clap(
+        long = "extra-fd",
+        value_name = "child:parent",
+        parse(try_from_str = parse_fd_pair)
+    )]
+    pub extra_fds: Vec<(RawFd, RawFd)>, // (child fd, parent fd)
+
     #[clap(short = 't', long, value_name = "milliseconds")]
     pub real_time_limit: Option<u64>,
 
-    #[clap(long, value_name = "seconds")]
-    pub rlimit_cpu: Option<u32>,
+    #[clap(long, value_name = "milliseconds")]
+    pub sample_interval: Option<u64>, // sample the cgroup counters while the program is running
 
-    #[clap(long, value_name = "bytes")]
-    pub rlimit_as: Option<u64>,
+    #[clap(
+        long = "rlimit",
+        value_name = "name=soft[:hard]",
+        parse(try_from_str = RLimit::try_from_str)
+    )]
+    pub rlimits: Vec<RLimit>, // hard defaults to soft
 
     #[clap(long, value_name = "bytes")]
-    pub rlimit_data: Option<u64>,
+    pub cg_limit_memory: Option<u64>,
+
+    #[clap(long, value_name = "bytes", requires = "cg-limit-memory")]
+    pub cg_limit_memsw: Option<u64>, // memory + swap, defaults to cg_limit_memory (no swap)
+
+    #[clap(
+        long,
+        value_name = "basis",
+        parse(try_from_str = MemoryBasis::try_from_str)
+    )]
+    pub memory_basis: Option<MemoryBasis>, // which peak is reported as `memory`, defaults to total
+
+    #[clap(long, value_name = "count")]
+    pub cg_limit_max_pids: Option<u32>,
+
+    #[clap(long, value_name = "microseconds")]
+    pub cpu_quota: Option<u64>, // CPU time in each period, summed over all cpus
+
+    #[clap(long, value_name = "microseconds", requires = "cpu-quota")]
+    pub cpu_period: Option<u64>, // defaults to 100000 (100 ms)
 
     #[clap(long, value_name = "bytes")]
-    pub rlimit_fsize: Option<u64>,
+    pub io_read_bps: Option<u64>, // on each disk backing chroot or bindmount_rw, bytes/s
 
     #[clap(long, value_name = "bytes")]
-    pub cg_limit_memory: Option<u64>,
+    pub io_write_bps: Option<u64>, // on each disk backing chroot or bindmount_rw, bytes/s
 
     #[clap(long, value_name = "count")]
-    pub cg_limit_max_pids: Option<u32>,
+    pub io_read_iops: Option<u64>, // on each disk backing chroot or bindmount_rw, ops/s
+
+    #[clap(long, value_name = "count")]
+    pub io_write_iops: Option<u64>, // on each disk backing chroot or bindmount_rw, ops/s
+
+    #[clap(long, value_name = "list")]
+    pub cpus: Option<String>, // cpu list like "0-3,8", defaults to all cpus
+
+    #[clap(long, value_name = "list")]
+    pub mems: Option<String>, // NUMA node list like "0-1", defaults to all nodes
 
     #[clap(
         long,
@@ -126,14 +231,59 @@ pub struct SandboxConfig {
     )]
     pub mount_tmpfs: Option<PathBuf>, // absolute (affected by chroot)
 
+    #[clap(long)]
+    pub mount_dev: bool, // mount a minimal /dev (affected by chroot)
+
+    #[clap(long, requires = "mount-dev")]
+    pub mount_devpts: bool, // requires mount_dev
+
+    #[clap(long, requires = "mount-dev")]
+    pub mount_devshm: bool, // requires mount_dev
+
+    #[clap(long)]
+    pub restrict_devices: bool, // deny all devices except the nodes of mount_dev and device_allow
+
+    #[clap(
+        long,
+        value_name = "rule",
+        requires = "restrict-devices",
+        parse(try_from_str = DeviceRule::try_from_str)
+    )]
+    pub device_allow: Vec<DeviceRule>,
+
     #[clap(long, value_name = "prio")]
     pub priority: Option<i8>,
 
+    #[clap(
+        long,
+        value_name = "policy",
+        parse(try_from_str = SchedPolicy::try_from_str)
+    )]
+    pub sched_policy: Option<SchedPolicy>,
+
+    #[clap(long, value_name = "adj", allow_hyphen_values = true)]
+    pub oom_score_adj: Option<i16>, // -1000 to 1000, higher is killed first
+
     #[clap(long)]
     pub seccomp_forbid_ipc: bool,
+
+    #[clap(long, value_name = "cap")]
+    pub cap_keep: Vec<String>, // capabilities kept by the program, all others are dropped
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+fn parse_mode(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s, 8).map_err(|_| "invalid octal mode".into())
+}
+
+fn parse_fd_pair(s: &str) -> Result<(RawFd, RawFd), String> {
+    let parse = |s: &str| s.parse::<RawFd>().map_err(|_| "invalid fd".to_owned());
+    match s.split_once(':') {
+        Some((child_fd, parent_fd)) => Ok((parse(child_fd)?, parse(parent_fd)?)),
+        None => Ok((parse(s)?, parse(s)?)),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BindMount {
     pub src: PathBuf, // absolute
     pub dst: PathBuf, // absolute (affected by chroot)
@@ -166,6 +316,253 @@ impl BindMount {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RLimitResource {
+    As,
+    Core,
+    Cpu,
+    Data,
+    Fsize,
+    Memlock,
+    Msgqueue,
+    Nofile,
+    Nproc,
+    Stack,
+}
+
+impl RLimitResource {
+    const ALL: &'static [RLimitResource] = &[
+        RLimitResource::As,
+        RLimitResource::Core,
+        RLimitResource::Cpu,
+        RLimitResource::Data,
+        RLimitResource::Fsize,
+        RLimitResource::Memlock,
+        RLimitResource::Msgqueue,
+        RLimitResource::Nofile,
+        RLimitResource::Nproc,
+        RLimitResource::Stack,
+    ];
+
+    /// parse resource names like `RLIMIT_STACK` or `stack`
+    fn try_from_str(s: &str) -> Result<Self, String> {
+        let lower = s.to_ascii_lowercase();
+        let short = lower.strip_prefix("rlimit_").unwrap_or(&lower);
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|res| res.as_str() == short)
+            .ok_or_else(|| format!("unknown rlimit resource: {}", s))
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            RLimitResource::As => "as",
+            RLimitResource::Core => "core",
+            RLimitResource::Cpu => "cpu",
+            RLimitResource::Data => "data",
+            RLimitResource::Fsize => "fsize",
+            RLimitResource::Memlock => "memlock",
+            RLimitResource::Msgqueue => "msgqueue",
+            RLimitResource::Nofile => "nofile",
+            RLimitResource::Nproc => "nproc",
+            RLimitResource::Stack => "stack",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RLimit {
+    pub resource: RLimitResource,
+    pub soft: u64, // `RLimit::INFINITY` means unlimited
+    pub hard: u64, // `RLimit::INFINITY` means unlimited
+}
+
+impl RLimit {
+    pub const INFINITY: u64 = u64::MAX;
+
+    fn try_from_str(s: &str) -> Result<Self, String> {
+        let parse = |s: &str| match s {
+            "unlimited" | "infinity" => Ok(Self::INFINITY),
+            _ => s
+                .parse::<u64>()
+                .map_err(|_| format!("invalid rlimit value: {}", s)),
+        };
+        let (name, values) = s
+            .split_once('=')
+            .ok_or_else(|| "invalid rlimit format".to_owned())?;
+        let resource = RLimitResource::try_from_str(name)?;
+        let (soft, hard) = match values.split_once(':') {
+            Some((soft, hard)) => (parse(soft)?, parse(hard)?),
+            None => (parse(values)?, parse(values)?),
+        };
+        Ok(Self {
+            resource,
+            soft,
+            hard,
+        })
+    }
+
+    fn to_cli_value(self) -> String {
+        let fmt = |v: u64| match v {
+            Self::INFINITY => "unlimited".to_owned(),
+            _ => v.to_string(),
+        };
+        format!(
+            "{}={}:{}",
+            self.resource.as_str(),
+            fmt(self.soft),
+            fmt(self.hard)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryBasis {
+    Total, // the peak usage of the memory cgroup, including page cache
+    Rss,   // the peak resident set size of the memory cgroup, without page cache
+}
+
+impl MemoryBasis {
+    fn try_from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "total" => Ok(MemoryBasis::Total),
+            "rss" => Ok(MemoryBasis::Rss),
+            _ => Err(format!("unknown memory basis: {}", s)),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            MemoryBasis::Total => "total",
+            MemoryBasis::Rss => "rss",
+        }
+    }
+}
+
+/// A rule of the devices cgroup, like `c 1:3 rw` or `b *:* r`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceRule {
+    pub kind: char,         // 'a' (all), 'b' (block) or 'c' (char)
+    pub major: Option<u32>, // `None` matches any major number
+    pub minor: Option<u32>, // `None` matches any minor number
+    pub access: String,     // a combination of 'r' (read), 'w' (write) and 'm' (mknod)
+}
+
+impl DeviceRule {
+    fn try_from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid device rule: {}", s);
+        let parse_num = |n: &str| match n {
+            "*" => Ok(None),
+            _ => n.parse::<u32>().map(Some).map_err(|_| invalid()),
+        };
+
+        let (kind, major, minor, access) = match s.split_whitespace().collect::<Vec<_>>()[..] {
+            [kind, numbers, access] => {
+                let (major, minor) = numbers.split_once(':').ok_or_else(invalid)?;
+                (kind, parse_num(major)?, parse_num(minor)?, access)
+            }
+            _ => return Err(invalid()),
+        };
+
+        let kind = match kind {
+            "a" | "b" | "c" => kind.chars().next().unwrap(),
+            _ => return Err(invalid()),
+        };
+        if access.is_empty() || !access.chars().all(|c| matches!(c, 'r' | 'w' | 'm')) {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            kind,
+            major,
+            minor,
+            access: access.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for DeviceRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let num = |n: Option<u32>| n.map_or_else(|| "*".to_owned(), |n| n.to_string());
+        write!(
+            f,
+            "{} {}:{} {}",
+            self.kind,
+            num(self.major),
+            num(self.minor),
+            self.access
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SchedPolicy {
+    Other,
+    Batch,
+    Idle,
+}
+
+impl SchedPolicy {
+    fn try_from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "other" => Ok(SchedPolicy::Other),
+            "batch" => Ok(SchedPolicy::Batch),
+            "idle" => Ok(SchedPolicy::Idle),
+            _ => Err(format!("unknown scheduling policy: {}", s)),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            SchedPolicy::Other => "other",
+            SchedPolicy::Batch => "batch",
+            SchedPolicy::Idle => "idle",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Namespace {
+    Uts,
+    Ipc,
+    Pid,
+    Net,
+    Cgroup,
+    Time,
+}
+
+impl Namespace {
+    pub const DEFAULT: &'static [Namespace] = &[Namespace::Uts, Namespace::Pid, Namespace::Net];
+
+    fn try_from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "uts" => Ok(Namespace::Uts),
+            "ipc" => Ok(Namespace::Ipc),
+            "pid" => Ok(Namespace::Pid),
+            "net" => Ok(Namespace::Net),
+            "cgroup" => Ok(Namespace::Cgroup),
+            "time" => Ok(Namespace::Time),
+            _ => Err(format!("unknown namespace: {}", s)),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Namespace::Uts => "uts",
+            Namespace::Ipc => "ipc",
+            Namespace::Pid => "pid",
+            Namespace::Net => "net",
+            Namespace::Cgroup => "cgroup",
+            Namespace::Time => "time",
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SandboxOutput {
     pub code: i32,
@@ -175,7 +572,43 @@ pub struct SandboxOutput {
     pub sys_time: u64,  // milliseconds
     pub user_time: u64, // milliseconds
 
-    pub memory: u64, // KiB
+    pub memory: u64, // KiB, peak_total or peak_rss according to memory_basis
+    pub swap: u64,   // KiB, peak memory+swap usage beyond peak memory usage
+
+    // KiB, resident memory of the cgroup without page cache.
+    // It is polled from `total_rss` in `memory.stat`, so a short peak may be missed.
+    // It is only tracked with memory_basis rss or sample_interval.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_rss: Option<u64>,
+    pub peak_total: u64, // KiB, including page cache
+    pub memory_basis: MemoryBasis,
+
+    // KiB, `ru_maxrss` of the largest process, which can not be lower than
+    // the footprint of the sandbox process before execve.
+    pub max_rss: u64,
+
+    pub throttled_time: u64, // milliseconds, always 0 without cpu_quota
+
+    // unmeasured without io limits, since the blkio controller is only joined with them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_read: Option<u64>, // bytes read from block devices, excluding page cache hits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_write: Option<u64>, // bytes written to block devices, excluding dirty pages not yet written back
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub samples: Vec<Sample>, // empty without sample_interval
+}
+
+/// A point of the timeline which is recorded every `sample_interval`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sample {
+    #[serde(rename = "t")]
+    pub time: u64, // milliseconds since the sandbox started
+    #[serde(rename = "cpu")]
+    pub cpu_time: u64, // milliseconds, user + sys
+    #[serde(rename = "mem")]
+    pub memory: u64, // KiB, including page cache
+    pub rss: u64, // KiB
 }
 
 impl SandboxOutput {
@@ -185,6 +618,10 @@ impl SandboxOutput {
 }
 
 impl SandboxConfig {
+    pub fn namespaces(&self) -> &[Namespace] {
+        self.namespaces.as_deref().unwrap_or(Namespace::DEFAULT)
+    }
+
     pub fn to_cli_cmd(&self) -> process::Command {
         let mut cmd = process::Command::new("carapace");
 
@@ -199,6 +636,11 @@ impl SandboxConfig {
                     cmd.arg($opt).arg($f.to_string());
                 }
             };
+            (@octal $opt: literal, $f: ident) => {
+                if let Some(ref $f) = self.$f {
+                    cmd.arg($opt).arg(format!("{:o}", $f));
+                }
+            };
             (@bindmount $opt: literal, $f: ident) => {
                 for mnt in &self.$f {
                     cmd.arg($opt);
@@ -210,6 +652,11 @@ impl SandboxConfig {
                     cmd.arg(s);
                 }
             };
+            (@fd_pair $opt: literal, $f: ident) => {
+                for &(child_fd, parent_fd) in &self.$f {
+                    cmd.arg($opt).arg(format!("{}:{}", child_fd, parent_fd));
+                }
+            };
             (@os_str @opt_arg $opt: literal, $f: ident) => {
                 if let Some(ref $f) = self.$f {
                     let mut s: OsString = $opt.into();
@@ -233,33 +680,76 @@ impl SandboxConfig {
         push!(@num "--uid", uid);
         push!(@num "--gid", gid);
 
-        push!(@num "--rlimit-cpu", rlimit_cpu);
-        push!(@num "--rlimit-as", rlimit_as);
-        push!(@num "--rlimit-data", rlimit_data);
-        push!(@num "--rlimit-fsize", rlimit_fsize);
+        for rlimit in &self.rlimits {
+            cmd.arg("--rlimit").arg(rlimit.to_cli_value());
+        }
 
         push!(@num "--cg-limit-memory", cg_limit_memory);
+        push!(@num "--cg-limit-memsw", cg_limit_memsw);
+        if let Some(basis) = self.memory_basis {
+            cmd.arg("--memory-basis").arg(basis.as_str());
+        }
         push!(@num "--cg-limit-max-pids", cg_limit_max_pids);
+        push!(@num "--cpu-quota", cpu_quota);
+        push!(@num "--cpu-period", cpu_period);
+        push!(@num "--io-read-bps", io_read_bps);
+        push!(@num "--io-write-bps", io_write_bps);
+        push!(@num "--io-read-iops", io_read_iops);
+        push!(@num "--io-write-iops", io_write_iops);
+        push!(@os_str "--cpus", cpus);
+        push!(@os_str "--mems", mems);
 
         push!(@bindmount "--bindmount-rw", bindmount_rw);
         push!(@bindmount "-b", bindmount_ro);
 
         push!(@os_str @opt_arg "--mount-proc", mount_proc);
         push!(@os_str @opt_arg "--mount-tmpfs", mount_tmpfs);
+        push!(@flag "--mount-dev", mount_dev);
+        push!(@flag "--mount-devpts", mount_devpts);
+        push!(@flag "--mount-devshm", mount_devshm);
+
+        push!(@flag "--restrict-devices", restrict_devices);
+        for rule in &self.device_allow {
+            cmd.arg("--device-allow").arg(rule.to_string());
+        }
 
         push!(@num "--priority", priority);
+        if let Some(policy) = self.sched_policy {
+            cmd.arg("--sched-policy").arg(policy.as_str());
+        }
+        push!(@num "--oom-score-adj", oom_score_adj);
 
         push!(@flag "--seccomp-forbid-ipc", seccomp_forbid_ipc);
 
+        push!(@os_str @multi "--cap-keep", cap_keep);
+
+        push!(@octal "--stdio-mode", stdio_mode);
+        push!(@flag "--stdio-append", stdio_append);
+
         push!(@num "--stdin-fd", stdin_fd);
         push!(@num "--stdout-fd", stdout_fd);
         push!(@num "--stderr-fd", stderr_fd);
+        push!(@fd_pair "--extra-fd", extra_fds);
 
         push!(@num "-t", real_time_limit);
+        push!(@num "--sample-interval", sample_interval);
+
+        if let Some(ref namespaces) = self.namespaces {
+            let names: Vec<_> = namespaces.iter().map(|ns| ns.as_str()).collect();
+            cmd.arg(format!("--namespaces={}", names.join(",")));
+        }
+
+        push!(@os_str "--hostname", hostname);
+        push!(@os_str "--domainname", domainname);
+        push!(@flag "--net-loopback", net_loopback);
 
         push!(@os_str "-c", chroot);
+        push!(@flag "--pivot-root", pivot_root);
+        push!(@os_str "--cwd", cwd);
 
         push!(@os_str @multi "-e", env);
+        push!(@flag "--search-path", search_path);
+        push!(@flag "--host-bin", host_bin);
 
         push!(@os_str "--stdin", stdin);
         push!(@os_str "--stdout", stdout);
@@ -2,12 +2,15 @@ use std::fs;
 use std::io::{self, Write};
 use std::os::unix::io::RawFd;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use carapace::{SandboxConfig, SandboxOutput};
 use clap::Clap;
 use nix::unistd;
 use tokio::runtime;
+use tokio::signal::unix::{signal, Signal, SignalKind};
 
 fn setup_tracing() {
     use tracing_error::ErrorLayer;
@@ -36,6 +39,18 @@ struct Opt {
     report_fd: Option<RawFd>,
 }
 
+async fn kill_on_signal(mut sigint: Signal, mut sigterm: Signal, interrupted: Arc<AtomicBool>) {
+    // NOTE: the signals are handled in a loop, so that later signals are not left unhandled
+    loop {
+        tokio::select! {
+            _ = sigint.recv() => {},
+            _ = sigterm.recv() => {},
+        }
+        interrupted.store(true, Ordering::SeqCst);
+        carapace::kill_all();
+    }
+}
+
 fn main() -> Result<()> {
     dotenv::dotenv().ok();
     setup_tracing();
@@ -45,14 +60,24 @@ fn main() -> Result<()> {
     let runtime = runtime::Builder::new_multi_thread()
         .worker_threads(1)
         .max_blocking_threads(1)
+        .enable_io()
         .enable_time()
         .build()?;
 
+    let interrupted = Arc::new(AtomicBool::new(false));
+
     let output: SandboxOutput = {
         let _enter = runtime.enter();
+        let sigint = signal(SignalKind::interrupt())?;
+        let sigterm = signal(SignalKind::terminate())?;
+        runtime.spawn(kill_on_signal(sigint, sigterm, Arc::clone(&interrupted)));
         carapace::run(&opt.config)?
     };
 
+    if interrupted.load(Ordering::SeqCst) {
+        anyhow::bail!("interrupted by signal");
+    }
+
     match (opt.report, opt.report_fd) {
         (Some(path), _) => {
             let mut report_file = fs::File::create(&path).with_context(|| {
@@ -1,77 +1,270 @@
-use crate::utils::{is_dir, libc_call, with_c_str};
+use crate::utils::{self, is_dir, libc_call, with_c_str, RawFd};
 
 use std::ffi::{CStr, CString};
 
 use std::os::unix::ffi::OsStrExt;
 
-use std::path::Path;
-use std::{fs, io, ptr};
+use std::path::{Component, Path, PathBuf};
+use std::{io, ptr};
 
-use anyhow::Result;
-use nix::fcntl::{self, OFlag};
+use anyhow::{Context, Result};
+use nix::fcntl::{self, FcntlArg, OFlag};
+use nix::mount::{self, MntFlags};
 
-use nix::sys::stat::Mode;
-use nix::unistd::{self, AccessFlags};
+use nix::sys::stat::{self, Mode};
+use nix::unistd;
+use path_absolutize::Absolutize;
+use scopeguard::guard;
 
-pub fn bind_mount(src_path: &Path, dst_path: &Path, recursive: bool, readonly: bool) -> Result<()> {
-    let src: &CStr = &CString::new(src_path.as_os_str().as_bytes())?;
-    let dst: &CStr = &CString::new(dst_path.as_os_str().as_bytes())?;
+/// An `O_PATH` descriptor of the directory in which mount targets are resolved.
+pub struct MountRoot(RawFd);
 
-    let src_is_dir = is_dir(src)?;
+/// A mount target resolved by `MountRoot`, which is mounted on through `/proc/self/fd`.
+pub struct MountTarget(RawFd);
 
-    let dst_exists = unistd::access(dst, AccessFlags::F_OK).is_ok();
-
-    if !dst_exists {
-        if src_is_dir {
-            fs::create_dir_all(dst_path)?;
-        } else {
-            if let Some(parent_dir) = dst_path.parent() {
-                fs::create_dir_all(parent_dir)?;
-            }
-            let fd = fcntl::open(
-                dst,
-                OFlag::O_CREAT | OFlag::O_RDONLY | OFlag::O_CLOEXEC,
-                Mode::from_bits_truncate(0o644),
-            )?;
-            let _ = unistd::close(fd);
+impl Drop for MountRoot {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.0);
+    }
+}
+
+impl Drop for MountTarget {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.0);
+    }
+}
+
+impl MountTarget {
+    pub fn path(&self) -> PathBuf {
+        format!("/proc/self/fd/{}", self.0).into()
+    }
+}
+
+impl MountRoot {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let flags = OFlag::O_PATH | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC;
+        let fd = fcntl::open(path, flags, Mode::empty())?;
+        Ok(Self(fd))
+    }
+
+    /// resolve `path` inside the root, and create the missing components.
+    /// The last component is created as a directory if `is_dir` is true, otherwise as an empty file.
+    ///
+    /// Symlinks are rejected, so that the target can not escape from the root.
+    pub fn resolve(&self, path: &Path, is_dir: bool) -> io::Result<MountTarget> {
+        const RESOLVE: u64 =
+            utils::RESOLVE_IN_ROOT | utils::RESOLVE_NO_SYMLINKS | utils::RESOLVE_NO_MAGICLINKS;
+
+        let open_path = |p: &Path| -> io::Result<MountTarget> {
+            let flags = OFlag::O_PATH | OFlag::O_CLOEXEC;
+            utils::openat2(self.0, p, flags, Mode::empty(), RESOLVE).map(MountTarget)
+        };
+
+        let path = path.absolutize_virtually("/")?;
+        let names: Vec<_> = path
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+
+        let mut target = fcntl::fcntl(self.0, FcntlArg::F_DUPFD_CLOEXEC(0)).map(MountTarget)?;
+        let mut prefix = PathBuf::from("/");
+
+        for (i, &name) in names.iter().enumerate() {
+            let parent = target;
+            prefix.push(name);
+
+            target = match open_path(&prefix) {
+                Ok(t) => t,
+                Err(err) if err.raw_os_error() == Some(libc::ENOENT) => {
+                    // `mkdirat` and `O_CREAT | O_EXCL` never follow symlinks
+                    if i + 1 < names.len() || is_dir {
+                        stat::mkdirat(parent.0, name, Mode::from_bits_truncate(0o755))?;
+                    } else {
+                        let flags =
+                            OFlag::O_CREAT | OFlag::O_EXCL | OFlag::O_RDONLY | OFlag::O_CLOEXEC;
+                        let fd = utils::openat2(
+                            parent.0,
+                            name.as_ref(),
+                            flags,
+                            Mode::from_bits_truncate(0o644),
+                            RESOLVE,
+                        )?;
+                        let _ = unistd::close(fd);
+                    }
+                    open_path(&prefix)?
+                }
+                Err(err) => return Err(err),
+            };
         }
+
+        Ok(target)
     }
+}
 
-    let do_mount = |flags| unsafe {
-        libc_call(|| libc::mount(src.as_ptr(), dst.as_ptr(), ptr::null(), flags, ptr::null()))
-    };
+fn to_c_string(path: &Path) -> Result<CString> {
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
 
-    do_mount(if recursive {
+unsafe fn raw_bind_mount(src: &CStr, dst: &CStr, flags: libc::c_ulong) -> io::Result<()> {
+    libc_call(|| libc::mount(src.as_ptr(), dst.as_ptr(), ptr::null(), flags, ptr::null()))?;
+    Ok(())
+}
+
+pub fn bind_mount(
+    src_path: &Path,
+    root: &MountRoot,
+    dst_path: &Path,
+    recursive: bool,
+    readonly: bool,
+) -> Result<()> {
+    let src: &CStr = &to_c_string(src_path)?;
+
+    let src_is_dir = is_dir(src)?;
+
+    let target = root.resolve(dst_path, src_is_dir)?;
+    let dst: &CStr = &to_c_string(&target.path())?;
+
+    let flags = if recursive {
         libc::MS_BIND | libc::MS_REC
     } else {
         libc::MS_BIND
-    })?;
+    };
+    unsafe { raw_bind_mount(src, dst, flags) }?;
 
     if readonly {
-        do_mount(libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY)?;
+        // NOTE: the old descriptor still refers to the covered directory
+        let target = root.resolve(dst_path, src_is_dir)?;
+        let dst: &CStr = &to_c_string(&target.path())?;
+        let flags = libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY;
+        unsafe { raw_bind_mount(src, dst, flags) }?;
     }
 
     Ok(())
 }
 
-unsafe fn special_mount(dst: &Path, fstype: &[u8]) -> io::Result<()> {
-    if !dst.exists() {
-        fs::create_dir_all(dst)?;
-    }
-    with_c_str(dst.as_os_str().as_bytes(), |dst| {
+unsafe fn special_mount(
+    root: &MountRoot,
+    dst: &Path,
+    fstype: &[u8],
+    flags: libc::c_ulong,
+    data: Option<&[u8]>,
+) -> io::Result<()> {
+    let target = root.resolve(dst, true)?;
+    with_c_str(target.path().as_os_str().as_bytes(), |dst| {
         let src = b"none\0".as_ptr().cast();
         let fstype = fstype.as_ptr().cast();
-        libc_call(|| libc::mount(src, dst.as_ptr(), fstype, 0, ptr::null()))?;
+        let data = data.map_or(ptr::null(), |d| d.as_ptr().cast());
+        libc_call(|| libc::mount(src, dst.as_ptr(), fstype, flags, data))?;
         Ok(())
     })
 }
 
-pub fn mount_proc(dst: &Path) -> io::Result<()> {
-    unsafe { special_mount(dst, b"proc\0".as_ref()) }
+pub fn mount_proc(root: &MountRoot, dst: &Path) -> io::Result<()> {
+    unsafe { special_mount(root, dst, b"proc\0".as_ref(), 0, None) }
+}
+
+pub fn mount_tmpfs(root: &MountRoot, dst: &Path) -> io::Result<()> {
+    unsafe { special_mount(root, dst, b"tmpfs\0".as_ref(), 0, None) }
+}
+
+/// char device nodes which are bind-mounted from the host `/dev` by `mount_dev`,
+/// with their major and minor numbers for `restrict_devices`
+pub const DEV_NODES: &[(&str, u32, u32)] = &[
+    ("null", 1, 3),
+    ("zero", 1, 5),
+    ("full", 1, 7),
+    ("random", 1, 8),
+    ("urandom", 1, 9),
+];
+
+/// char devices of a devpts instance, `ptmx` and the ptys, as `(major, minor)`.
+/// `None` matches any minor number.
+pub const DEVPTS_DEVICES: &[(u32, Option<u32>)] = &[(5, Some(2)), (136, None)];
+
+const DEV_SYMLINKS: &[(&str, &str)] = &[
+    ("fd", "/proc/self/fd"),
+    ("stdin", "/proc/self/fd/0"),
+    ("stdout", "/proc/self/fd/1"),
+    ("stderr", "/proc/self/fd/2"),
+];
+
+/// mount a minimal `/dev` on `dst`:
+/// a tmpfs populated with the allowlisted device nodes and the standard symlinks
+pub fn mount_dev(root: &MountRoot, dst: &Path, devpts: bool, devshm: bool) -> Result<()> {
+    // NOTE:
+    // The host nodes are opened before mounting the tmpfs,
+    // because `dst` may be the host `/dev` itself when there is no chroot.
+    let mut nodes = guard(Vec::with_capacity(DEV_NODES.len()), |nodes| {
+        for (_, fd) in nodes {
+            let _ = unistd::close(fd);
+        }
+    });
+    for &(name, _, _) in DEV_NODES {
+        let path = Path::new("/dev").join(name);
+        let fd = fcntl::open(&path, OFlag::O_PATH | OFlag::O_CLOEXEC, Mode::empty())
+            .with_context(|| format!("failed to open device node: path = {}", path.display()))?;
+        nodes.push((name, fd));
+    }
+
+    unsafe {
+        let flags = libc::MS_NOSUID | libc::MS_NOEXEC;
+        special_mount(root, dst, b"tmpfs\0", flags, Some(b"mode=755\0"))?;
+    }
+
+    for &(name, fd) in nodes.iter() {
+        let src = PathBuf::from(format!("/proc/self/fd/{}", fd));
+        bind_mount(&src, root, &dst.join(name), false, false)
+            .with_context(|| format!("failed to bind device node: name = {}", name))?;
+    }
+
+    let dev = root.resolve(dst, true)?;
+    for &(link, target) in DEV_SYMLINKS {
+        unistd::symlinkat(target, Some(dev.0), link)?;
+    }
+
+    if devpts {
+        unsafe {
+            let flags = libc::MS_NOSUID | libc::MS_NOEXEC;
+            let data = b"newinstance,ptmxmode=0666,mode=0620\0";
+            special_mount(root, &dst.join("pts"), b"devpts\0", flags, Some(data))?;
+        }
+        unistd::symlinkat("pts/ptmx", Some(dev.0), "ptmx")?;
+    }
+
+    if devshm {
+        unsafe {
+            let flags = libc::MS_NOSUID | libc::MS_NODEV;
+            special_mount(
+                root,
+                &dst.join("shm"),
+                b"tmpfs\0",
+                flags,
+                Some(b"mode=1777\0"),
+            )?;
+        }
+    }
+
+    Ok(())
 }
 
-pub fn mount_tmpfs(dst: &Path) -> io::Result<()> {
-    unsafe { special_mount(dst, b"tmpfs\0".as_ref()) }
+/// switch the root mount to `new_root` and detach the old root,
+/// so that the host filesystem is no longer reachable from the mount namespace
+/// https://man7.org/linux/man-pages/man2/pivot_root.2.html
+pub fn pivot_root(new_root: &Path) -> Result<()> {
+    // `new_root` must be a mount point
+    let path: &CStr = &to_c_string(new_root)?;
+    unsafe { raw_bind_mount(path, path, libc::MS_BIND | libc::MS_REC) }?;
+
+    // stack the old root on top of the new root, and then unmount it
+    unistd::chdir(new_root)?;
+    unistd::pivot_root(".", ".")?;
+    mount::umount2(".", MntFlags::MNT_DETACH)?;
+    unistd::chdir("/")?;
+
+    Ok(())
 }
 
 /// prevent propagation of mount events to other mount namespaces
@@ -0,0 +1,37 @@
+use crate::utils::libc_call;
+
+use std::io;
+
+use nix::unistd;
+use scopeguard::guard;
+
+/// bring up the loopback interface in the current network namespace
+/// https://man7.org/linux/man-pages/man7/netdevice.7.html
+pub fn bring_up_loopback() -> io::Result<()> {
+    #[repr(C)]
+    struct IfReq {
+        name: [u8; libc::IFNAMSIZ],
+        flags: libc::c_short,
+        _pad: [u8; 22], // the rest of the union in `struct ifreq`
+    }
+
+    let sock = libc_call(|| unsafe {
+        libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0)
+    })?;
+    let sock = guard(sock as libc::c_int, |fd| {
+        let _ = unistd::close(fd);
+    });
+
+    let mut ifr = IfReq {
+        name: [0; libc::IFNAMSIZ],
+        flags: 0,
+        _pad: [0; 22],
+    };
+    ifr.name[..2].copy_from_slice(b"lo");
+
+    libc_call(|| unsafe { libc::ioctl(*sock, libc::SIOCGIFFLAGS, &mut ifr) })?;
+    ifr.flags |= (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+    libc_call(|| unsafe { libc::ioctl(*sock, libc::SIOCSIFFLAGS, &ifr) })?;
+
+    Ok(())
+}
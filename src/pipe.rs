@@ -36,6 +36,27 @@ pub fn create() -> io::Result<(PipeTx, PipeRx)> {
 }
 
 impl PipeTx {
+    pub fn move_above(&mut self, min_fd: RawFd) -> io::Result<()> {
+        if self.0 >= min_fd {
+            return Ok(());
+        }
+        let fd = libc_call(|| unsafe { libc::fcntl(self.0, libc::F_DUPFD_CLOEXEC, min_fd) })?;
+        let _ = unsafe { libc::close(self.0) };
+        self.0 = fd as RawFd;
+        Ok(())
+    }
+
+    /// whether the receiver has been closed, for example, the parent has exited
+    pub fn is_peer_closed(&self) -> io::Result<bool> {
+        let mut fds = [libc::pollfd {
+            fd: self.0,
+            events: 0,
+            revents: 0,
+        }];
+        libc_call(|| unsafe { libc::poll(fds.as_mut_ptr(), 1, 0) })?;
+        Ok(fds[0].revents & libc::POLLHUP != 0)
+    }
+
     pub fn write_error(self, err: anyhow::Error) -> io::Result<()> {
         let mut buf = Vec::new();
         write!(buf, "{:?}", err).unwrap();
@@ -1,14 +1,17 @@
 use crate::utils::libc_call;
+use crate::Namespace;
 
 use std::mem::{self, ManuallyDrop};
 
 use std::panic::{self, AssertUnwindSafe};
-
+use std::time::Instant;
 use std::{io, ptr};
 
+use anyhow::{Context, Result};
 use nix::sched::CloneFlags;
 
 use nix::unistd::Pid;
+use tracing::trace;
 
 pub fn wait_child(child_pid: Pid) -> io::Result<(i32, i32)> {
     unsafe fn waitid(
@@ -45,6 +48,50 @@ pub fn wait_child(child_pid: Pid) -> io::Result<(i32, i32)> {
     }
 }
 
+/// wait for the child like `wait_child`, and also return its resource usage
+pub fn wait4_child(child_pid: Pid) -> io::Result<((i32, i32), libc::rusage)> {
+    let mut status: libc::c_int = 0;
+    let mut rusage: libc::rusage = unsafe { mem::zeroed() };
+
+    libc_call(|| unsafe { libc::wait4(child_pid.as_raw(), &mut status, 0, &mut rusage) })?;
+
+    let ret = if libc::WIFEXITED(status) {
+        (libc::WEXITSTATUS(status), 0)
+    } else {
+        (0, libc::WTERMSIG(status))
+    };
+    Ok((ret, rusage))
+}
+
+pub const CLONE_NEWTIME: libc::c_int = 0x80;
+
+fn namespace_flag(ns: Namespace) -> libc::c_int {
+    match ns {
+        Namespace::Uts => libc::CLONE_NEWUTS,
+        Namespace::Ipc => libc::CLONE_NEWIPC,
+        Namespace::Pid => libc::CLONE_NEWPID,
+        Namespace::Net => libc::CLONE_NEWNET,
+        Namespace::Cgroup => libc::CLONE_NEWCGROUP,
+        Namespace::Time => CLONE_NEWTIME,
+    }
+}
+
+pub fn unshare_namespace(ns: Namespace) -> Result<()> {
+    let t0 = Instant::now();
+    libc_call(|| unsafe { libc::unshare(namespace_flag(ns)) })
+        .with_context(|| format!("failed to unshare namespace: ns = {:?}", ns))?;
+    let duration = t0.elapsed();
+    trace!(?ns, ?duration, "unshare namespace");
+    Ok(())
+}
+
+/// kill the current process when its parent thread exits
+/// https://man7.org/linux/man-pages/man2/prctl.2.html
+pub fn set_parent_death_signal(signal: libc::c_int) -> io::Result<()> {
+    libc_call(|| unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, signal as libc::c_ulong, 0, 0, 0) })?;
+    Ok(())
+}
+
 pub unsafe fn clone_proc<F: FnOnce() -> libc::c_int>(
     cb: F,
     stack: &mut [u8],
@@ -56,7 +103,7 @@ pub unsafe fn clone_proc<F: FnOnce() -> libc::c_int>(
         F: FnOnce() -> libc::c_int + Sized,
     {
         let f = unsafe { ptr::read(data.cast::<F>()) };
-        panic::catch_unwind(AssertUnwindSafe(|| f())).unwrap_or(101)
+        panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(101)
     }
 
     let mut f = ManuallyDrop::new(cb);
@@ -75,3 +122,43 @@ pub unsafe fn clone_proc<F: FnOnce() -> libc::c_int>(
 
     Ok(Pid::from_raw(ret? as _))
 }
+
+/// clone the current process like `fork`, with the flags which `clone` can not take,
+/// for example, `CLONE_NEWTIME` which conflicts with the exit signal of `clone`.
+/// https://man7.org/linux/man-pages/man2/clone3.2.html
+pub unsafe fn clone3_proc<F: FnOnce() -> libc::c_int>(
+    cb: F,
+    flags: u64,
+    signal: libc::c_int,
+) -> io::Result<Pid> {
+    #[repr(C)]
+    #[derive(Default)]
+    struct CloneArgs {
+        flags: u64,
+        pidfd: u64,
+        child_tid: u64,
+        parent_tid: u64,
+        exit_signal: u64,
+        stack: u64,
+        stack_size: u64,
+        tls: u64,
+    }
+
+    let mut args = CloneArgs {
+        flags,
+        exit_signal: signal as u64,
+        ..CloneArgs::default()
+    };
+
+    // NOTE: without a stack, the child continues on a copy of the parent stack like `fork`
+    let size = mem::size_of::<CloneArgs>();
+    let ret =
+        libc_call(|| libc::syscall(libc::SYS_clone3, &mut args as *mut CloneArgs, size) as _)?;
+
+    if ret == 0 {
+        let code = panic::catch_unwind(AssertUnwindSafe(cb)).unwrap_or(101);
+        libc::_exit(code);
+    }
+
+    Ok(Pid::from_raw(ret as _))
+}
@@ -1,22 +1,64 @@
-use crate::cgroup_v1::Cgroup;
-use crate::child::run_child;
-use crate::pipe::{self, PipeRx};
-use crate::proc::{clone_proc, wait_child};
-use crate::signal;
-use crate::{SandboxConfig, SandboxOutput};
-
-use std::ptr;
-use std::time::Instant;
+use crate::capability::CapSet;
+use crate::cgroup_pool;
+use crate::cgroup_v1::{self, Cgroup, Controllers};
+use crate::child::{reserved_fd_end, run_child};
+use crate::pipe;
+use crate::proc::{
+    clone3_proc, clone_proc, set_parent_death_signal, wait4_child, wait_child, CLONE_NEWTIME,
+};
+use crate::sample::{self, PeakTracker, Sampler};
+use crate::signal::{self, ActiveClock, Registered};
+use crate::utils::RawFd;
+use crate::{
+    MemoryBasis, Namespace, RLimitResource, Sample, SandboxConfig, SandboxOutput, SchedPolicy,
+};
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{ptr, thread};
 
 use aligned_utils::bytes::AlignedBytes;
 use anyhow::{Context, Result};
-use nix::sched::CloneFlags;
+use nix::errno::Errno;
+use nix::fcntl::{self, FcntlArg, OFlag};
+use nix::sched::{CloneFlags, CpuSet};
+use nix::sys::signal::Signal;
+use nix::sys::stat;
 use nix::unistd::Pid;
-use scopeguard::guard;
+use tokio::task::JoinHandle;
 use tracing::{debug, trace, warn};
 
-#[tracing::instrument(level = "trace", err, skip(config), fields(nonce))]
 pub fn run(config: &SandboxConfig) -> Result<SandboxOutput> {
+    // the sandbox can not be paused, so the freezer is not needed
+    spawn_sandbox(config, false)?.wait()
+}
+
+pub fn spawn(config: &SandboxConfig) -> Result<Sandbox> {
+    spawn_sandbox(config, true)
+}
+
+/// A running sandbox, which is created by `spawn`.
+///
+/// The sandbox is killed and cleaned up if it is dropped without `wait`.
+///
+/// It is not `Send`, because the child is killed by `PR_SET_PDEATHSIG`
+/// when the thread which has spawned it exits.
+pub struct Sandbox {
+    config: SandboxConfig,
+    child_pid: Pid,
+    t0: Instant,
+    clock: Arc<ActiveClock>,
+    cgroup: Option<Cgroup>, // taken by `wait` or `drop`
+    killer: Option<JoinHandle<()>>,
+    sampler: Option<Sampler>,
+    peak_tracker: Option<PeakTracker>, // taken by `wait`
+    _registered: Registered,
+    _not_send: PhantomData<*const ()>,
+}
+
+#[tracing::instrument(level = "trace", err, skip(config), fields(nonce))]
+fn spawn_sandbox(config: &SandboxConfig, freezable: bool) -> Result<Sandbox> {
     let nonce: u32 = rand::random();
     tracing::Span::current().record("nonce", &nonce);
 
@@ -24,17 +66,35 @@ pub fn run(config: &SandboxConfig) -> Result<SandboxOutput> {
 
     validate(config)?;
 
-    let cgroup = Cgroup::create(&format!("carapace_{}", nonce))?;
+    let controllers = cg_controllers(config, freezable);
+    let cgroup = cgroup_pool::acquire(&format!("carapace_{}", nonce), controllers)?;
 
     let (pipe_tx, pipe_rx) = pipe::create().context("failed to create pipe")?;
 
     let (t0, child_pid) = {
         let clone_cb = || unsafe {
-            let pipe_tx = ptr::read(&pipe_tx);
+            let mut pipe_tx = ptr::read(&pipe_tx);
             let pipe_rx = ptr::read(&pipe_rx);
             drop(pipe_rx);
 
-            let result = run_child(config, &cgroup);
+            if let Err(err) = set_parent_death_signal(libc::SIGKILL) {
+                let err = anyhow::Error::new(err).context("failed to set parent death signal");
+                let _ = pipe_tx.write_error(err);
+                return 101;
+            }
+
+            // NOTE:
+            // The parent may have exited before `PR_SET_PDEATHSIG` takes effect.
+            // `getppid` can not detect it because it returns 0 in a new pid namespace,
+            // so we check whether the parent side of the pipe has been closed instead.
+            if pipe_tx.is_peer_closed().unwrap_or(true) {
+                return 101;
+            }
+
+            let result = pipe_tx
+                .move_above(reserved_fd_end(config))
+                .context("failed to move pipe")
+                .and_then(|_| run_child(config, &cgroup, &pipe_tx));
 
             let _ = pipe_tx.write_error(result.unwrap_err());
             101
@@ -42,10 +102,16 @@ pub fn run(config: &SandboxConfig) -> Result<SandboxOutput> {
 
         let mut stack = AlignedBytes::new_zeroed(128 * 1024, 16);
 
-        let flags: CloneFlags = CloneFlags::CLONE_NEWNS
-            | CloneFlags::CLONE_NEWUTS
-            | CloneFlags::CLONE_NEWPID
-            | CloneFlags::CLONE_NEWNET;
+        let namespaces = config.namespaces();
+
+        // NOTE:
+        // Only the mount, pid and time namespaces are created by clone.
+        // The others are unshared by the child one by one, so that their setup costs can be traced.
+        // The cgroup namespace is unshared after the child has joined its cgroups.
+        let mut flags: CloneFlags = CloneFlags::CLONE_NEWNS;
+        if namespaces.contains(&Namespace::Pid) {
+            flags |= CloneFlags::CLONE_NEWPID;
+        }
 
         // NOTE:
         // When the last process in an IPC namespace exits,
@@ -53,19 +119,95 @@ pub fn run(config: &SandboxConfig) -> Result<SandboxOutput> {
         // But it can cause an overhead (about 30ms) of shutting down the last process,
         // which increase the `real_time` number in sandbox output.
         // Is it a kernel bug?
+        // So the IPC namespace is not enabled by default.
         //
         // REF: https://man7.org/linux/man-pages/man7/ipc_namespaces.7.html
 
         let t0 = Instant::now();
 
-        let child_pid = unsafe { clone_proc(clone_cb, &mut *stack, flags, libc::SIGCHLD) }
-            .context("failed to fork")?;
+        let child_pid = if namespaces.contains(&Namespace::Time) {
+            // NOTE:
+            // A process can not enter a new time namespace by itself.
+            // `unshare` would move the later children of the calling thread into the namespace,
+            // and `setns` can not move them back in a multi-threaded process.
+            // So the child is created in the new time namespace by `clone3`.
+            //
+            // REF: https://man7.org/linux/man-pages/man7/time_namespaces.7.html
+            let flags = flags.bits() as u64 | CLONE_NEWTIME as u64;
+            unsafe { clone3_proc(clone_cb, flags, libc::SIGCHLD) }
+        } else {
+            unsafe { clone_proc(clone_cb, &mut stack, flags, libc::SIGCHLD) }
+        }
+        .context("failed to fork")?;
+
+        let clone_duration = t0.elapsed();
+        trace!(?flags, ?clone_duration);
 
         (t0, child_pid)
     };
 
     drop(pipe_tx);
-    run_parent(config, child_pid, t0, pipe_rx, cgroup)
+
+    trace!(?child_pid);
+
+    let registered = signal::register(child_pid, cgroup.freezer());
+    let clock = Arc::new(ActiveClock::new(t0));
+
+    let killer = config
+        .real_time_limit
+        .map(|real_time_limit| signal::async_kill(child_pid, real_time_limit, Arc::clone(&clock)));
+
+    let sampler = config
+        .sample_interval
+        .map(|interval_ms| Sampler::spawn(&cgroup, interval_ms, t0));
+
+    // the tracker needs a tokio runtime, so it is only started when the peak rss is asked for
+    let memory_basis = config.memory_basis.unwrap_or(MemoryBasis::Total);
+    let peak_tracker = (memory_basis == MemoryBasis::Rss || config.sample_interval.is_some())
+        .then(|| PeakTracker::spawn(&cgroup));
+
+    // the child is killed and cleaned up by `drop` on errors
+    let sandbox = Sandbox {
+        config: config.clone(),
+        child_pid,
+        t0,
+        clock,
+        cgroup: Some(cgroup),
+        killer,
+        sampler,
+        peak_tracker,
+        _registered: registered,
+        _not_send: PhantomData,
+    };
+
+    trace!("start to receive child result");
+
+    let child_result = pipe_rx
+        .read_result()
+        .context("failed to read child result")?;
+
+    let child_result_duration = t0.elapsed();
+    trace!(?child_result_duration);
+
+    child_result.context("child process failed")?;
+
+    Ok(sandbox)
+}
+
+/// the optional controllers which are required by the config
+fn cg_controllers(config: &SandboxConfig, freezable: bool) -> Controllers {
+    let io_limits = [
+        config.io_read_bps,
+        config.io_write_bps,
+        config.io_read_iops,
+        config.io_write_iops,
+    ];
+    Controllers {
+        cpuset: config.cpus.is_some() || config.mems.is_some(),
+        blkio: io_limits.iter().any(Option::is_some),
+        devices: config.restrict_devices,
+        freezer: freezable,
+    }
 }
 
 fn validate(config: &SandboxConfig) -> Result<()> {
@@ -75,6 +217,112 @@ fn validate(config: &SandboxConfig) -> Result<()> {
         }
     }
 
+    if config.sched_policy == Some(SchedPolicy::Idle) && config.priority.is_some() {
+        anyhow::bail!("priority has no effect with the idle scheduling policy");
+    }
+
+    if config.sample_interval == Some(0) {
+        anyhow::bail!("sample interval must be positive");
+    }
+
+    if let Some(adj) = config.oom_score_adj {
+        if !(-1000..=1000).contains(&adj) {
+            anyhow::bail!(
+                "oom_score_adj must be in the range -1000 to 1000: adj = {}",
+                adj
+            );
+        }
+    }
+
+    if config.cg_limit_memsw.is_some() && config.cg_limit_memory.is_none() {
+        anyhow::bail!("memory+swap limit requires memory limit");
+    }
+
+    if let (Some(memory), Some(memsw)) = (config.cg_limit_memory, config.cg_limit_memsw) {
+        if memsw < memory {
+            anyhow::bail!(
+                "memory+swap limit must not be less than memory limit: memsw = {}, memory = {}",
+                memsw,
+                memory
+            );
+        }
+    }
+
+    for (i, rlimit) in config.rlimits.iter().enumerate() {
+        if config.rlimits[..i]
+            .iter()
+            .any(|r| r.resource == rlimit.resource)
+        {
+            anyhow::bail!(
+                "rlimit is set more than once: rlimit = {:?}",
+                rlimit.resource
+            )
+        }
+        if rlimit.soft > rlimit.hard {
+            anyhow::bail!(
+                "soft rlimit must not exceed hard rlimit: rlimit = {:?}, soft = {}, hard = {}",
+                rlimit.resource,
+                rlimit.soft,
+                rlimit.hard
+            )
+        }
+        // the extra fds are passed after the rlimits are set, and `dup2` can not exceed NOFILE
+        let fd_end = reserved_fd_end(config) as u64;
+        if rlimit.resource == RLimitResource::Nofile
+            && !config.extra_fds.is_empty()
+            && rlimit.soft < fd_end
+        {
+            anyhow::bail!(
+                "nofile rlimit must exceed the extra fds: soft = {}, max fd = {}",
+                rlimit.soft,
+                fd_end - 1
+            )
+        }
+    }
+
+    // the limits of `cpu.cfs_quota_us` and `cpu.cfs_period_us`
+    if let Some(cpu_quota) = config.cpu_quota {
+        if cpu_quota < 1000 {
+            anyhow::bail!(
+                "cpu quota must be at least 1000us: cpu_quota = {}",
+                cpu_quota
+            )
+        }
+    }
+    if config.cpu_period.is_some() && config.cpu_quota.is_none() {
+        anyhow::bail!("cpu period requires cpu quota");
+    }
+    if let Some(cpu_period) = config.cpu_period {
+        if !(1000..=1_000_000).contains(&cpu_period) {
+            anyhow::bail!(
+                "cpu period must be in the range 1000us to 1s: cpu_period = {}",
+                cpu_period
+            )
+        }
+    }
+
+    for (name, list) in &[("cpus", &config.cpus), ("mems", &config.mems)] {
+        if let Some(list) = list {
+            match cgroup_v1::parse_list(list) {
+                Some(items) if !items.is_empty() => {}
+                _ => anyhow::bail!("invalid {} list: {} = {:?}", name, name, list),
+            }
+        }
+    }
+
+    if let Some(ref cpus) = config.cpus {
+        let max_cpu = cgroup_v1::parse_list(cpus).into_iter().flatten().max();
+        if max_cpu >= Some(CpuSet::count()) {
+            anyhow::bail!("cpu is out of range: cpus = {:?}", cpus)
+        }
+    }
+
+    if let Some(mode) = config.stdio_mode {
+        if mode & !0o777 != 0 {
+            anyhow::bail!("stdio mode must be permission bits: mode = {:o}", mode);
+        }
+    }
+
     for mnt in config.bindmount_rw.iter().chain(config.bindmount_ro.iter()) {
         if !mnt.src.is_absolute() || !mnt.dst.is_absolute() {
             anyhow::bail!(
@@ -85,6 +333,12 @@ fn validate(config: &SandboxConfig) -> Result<()> {
         }
     }
 
+    if let Some(ref cwd) = config.cwd {
+        if !cwd.is_absolute() {
+            anyhow::bail!("cwd must be absolute: cwd = {}", cwd.display())
+        }
+    }
+
     for mnt in config.mount_proc.iter().chain(&config.mount_tmpfs) {
         if !mnt.is_absolute() {
             anyhow::bail!(
@@ -94,76 +348,302 @@ fn validate(config: &SandboxConfig) -> Result<()> {
         }
     }
 
+    let stdio_fds = [
+        ("stdin", config.stdin_fd, true),
+        ("stdout", config.stdout_fd, false),
+        ("stderr", config.stderr_fd, false),
+    ];
+    for &(name, fd, is_input) in &stdio_fds {
+        if let Some(fd) = fd {
+            check_stdio_fd(name, fd, is_input)?;
+        }
+    }
+
+    for (i, &(child_fd, parent_fd)) in config.extra_fds.iter().enumerate() {
+        if child_fd <= libc::STDERR_FILENO {
+            anyhow::bail!("extra fd must not be a stdio fd: fd = {}", child_fd)
+        }
+        if config.extra_fds[..i].iter().any(|&(c, _)| c == child_fd) {
+            anyhow::bail!("extra fd is passed more than once: fd = {}", child_fd)
+        }
+        if let Err(err) = fcntl::fcntl(parent_fd, FcntlArg::F_GETFD) {
+            if err == Errno::EBADF {
+                anyhow::bail!("extra fd is not open: fd = {}", parent_fd)
+            }
+            return Err(err)
+                .with_context(|| format!("failed to check extra fd: fd = {}", parent_fd));
+        }
+    }
+
+    CapSet::from_names(&config.cap_keep).map_err(anyhow::Error::msg)?;
+
+    for name in config.hostname.iter().chain(&config.domainname) {
+        if !config.namespaces().contains(&Namespace::Uts) {
+            anyhow::bail!("hostname and domainname require the UTS namespace")
+        }
+        if name.len() > 64 {
+            anyhow::bail!(
+                "hostname and domainname must not exceed 64 bytes: name = {:?}",
+                name
+            )
+        }
+    }
+
+    if config.net_loopback && !config.namespaces().contains(&Namespace::Net) {
+        anyhow::bail!("net_loopback requires the network namespace")
+    }
+
+    if config.pivot_root && config.chroot.is_none() {
+        anyhow::bail!("pivot_root requires chroot")
+    }
+
+    if (config.mount_devpts || config.mount_devshm) && !config.mount_dev {
+        anyhow::bail!("mount_devpts and mount_devshm require mount_dev")
+    }
+
+    if !config.device_allow.is_empty() && !config.restrict_devices {
+        anyhow::bail!("device_allow requires restrict_devices")
+    }
+
     Ok(())
 }
 
-fn run_parent(
-    config: &SandboxConfig,
-    child_pid: Pid,
-    t0: Instant,
-    pipe_rx: PipeRx,
-    cgroup: Cgroup,
-) -> Result<SandboxOutput> {
-    trace!(?child_pid);
-
-    let killer: Option<_> = if let Some(real_time_limit) = config.real_time_limit {
-        let handle = signal::async_kill(child_pid, real_time_limit);
-        Some(guard(handle, |h| h.abort()))
-    } else {
-        None
+fn check_stdio_fd(name: &str, fd: RawFd, is_input: bool) -> Result<()> {
+    let flags = match fcntl::fcntl(fd, FcntlArg::F_GETFL) {
+        Ok(flags) => OFlag::from_bits_truncate(flags),
+        Err(Errno::EBADF) => anyhow::bail!("{} fd is not open: fd = {}", name, fd),
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to check {} fd: fd = {}", name, fd))
+        }
     };
 
-    trace!("start to receive child result");
+    let st = stat::fstat(fd).with_context(|| format!("failed to stat {} fd: fd = {}", name, fd))?;
+    if st.st_mode & libc::S_IFMT == libc::S_IFDIR {
+        anyhow::bail!("{} fd is a directory: fd = {}", name, fd)
+    }
 
-    let child_result = pipe_rx
-        .read_result()
-        .context("failed to read child result")?;
+    let access = flags & OFlag::O_ACCMODE;
+    if is_input && access == OFlag::O_WRONLY {
+        anyhow::bail!("{} fd is not readable: fd = {}", name, fd)
+    }
+    if !is_input && access == OFlag::O_RDONLY {
+        anyhow::bail!("{} fd is not writable: fd = {}", name, fd)
+    }
 
-    let child_result_duration = t0.elapsed();
-    trace!(?child_result_duration);
+    Ok(())
+}
 
-    child_result.context("child process failed")?;
+impl Sandbox {
+    fn cgroup(&self) -> &Cgroup {
+        self.cgroup.as_ref().unwrap()
+    }
 
-    let wait_t0 = Instant::now();
-    let (code, signal) = wait_child(child_pid).context("failed to wait4")?;
-    let wait_duration = wait_t0.elapsed();
-    let real_duration = t0.elapsed();
-    drop(killer);
+    fn freezer(&self) -> Result<&str> {
+        self.cgroup().freezer().context("sandbox is not freezable")
+    }
 
-    trace!(?code, ?signal, ?real_duration, ?wait_duration);
+    /// freeze all processes in the sandbox.
+    ///
+    /// The frozen time is not counted by `real_time` and the real time limit.
+    pub fn pause(&self) -> Result<()> {
+        let freezer = self.freezer()?;
+        Cgroup::write_type(freezer, "freezer.state", "FROZEN")
+            .context("failed to freeze cgroup")?;
+
+        if let Err(err) = wait_frozen(freezer) {
+            // a partially frozen sandbox is thawed, so that it keeps running
+            let _ = Cgroup::write_type(freezer, "freezer.state", "THAWED");
+            return Err(err);
+        }
 
-    let m = {
-        let ret1 = cg_collect(&cgroup).context("failed to collect metrics from cgroup");
-        let ret2 = cg_cleanup(cgroup).context("failed to cleanup cgroup");
-        ret2.and(ret1)?
-    };
+        self.clock.freeze();
+        trace!(child_pid = ?self.child_pid, "sandbox paused");
+        Ok(())
+    }
+
+    /// thaw all processes in the sandbox
+    pub fn resume(&self) -> Result<()> {
+        let freezer = self.freezer()?;
+        Cgroup::write_type(freezer, "freezer.state", "THAWED").context("failed to thaw cgroup")?;
+
+        self.clock.thaw();
+        trace!(child_pid = ?self.child_pid, "sandbox resumed");
+        Ok(())
+    }
+
+    /// read the current counters of the sandbox
+    pub fn snapshot(&self) -> Result<Sample> {
+        sample::read_sample(self.cgroup(), self.t0)
+    }
+
+    /// wait for the sandbox to exit, which resumes it first if it is paused
+    pub fn wait(mut self) -> Result<SandboxOutput> {
+        if self.clock.is_frozen() {
+            self.resume()?;
+        }
+
+        let child_pid = self.child_pid;
+
+        // NOTE:
+        // The rss is freed as soon as the child exits, so it is read once more before waiting.
+        // Otherwise a run which is shorter than the polling interval would report nothing.
+        if let Some(ref tracker) = self.peak_tracker {
+            if let Err(err) = tracker.update() {
+                warn!(%err, "failed to read rss of cgroup");
+            }
+        }
+
+        let wait_t0 = Instant::now();
+        let ((code, signal), rusage) = wait4_child(child_pid).context("failed to wait4")?;
+        let wait_duration = wait_t0.elapsed();
+        let real_duration = self.clock.elapsed();
+        if let Some(killer) = self.killer.take() {
+            killer.abort();
+        }
+        let samples = self.sampler.take().map(Sampler::finish).unwrap_or_default();
+        let peak_rss = self.peak_tracker.take().map(PeakTracker::finish);
 
-    Ok(SandboxOutput {
-        code,
-        signal,
-        real_time: real_duration.as_millis() as u64,
-        sys_time: m.sys_time / 1_000_000,   // ns => ms
-        user_time: m.user_time / 1_000_000, // ns => ms
-        memory: m.memory / 1024,            // bytes => KiB
-    })
+        trace!(?code, ?signal, ?real_duration, ?wait_duration);
+
+        let config = &self.config;
+        let cgroup = self.cgroup.take().unwrap();
+
+        let m = {
+            let ret1 = cg_collect(config, &cgroup).context("failed to collect metrics from cgroup");
+            // `throttled_time` in `cpu.stat` can not be reset
+            let reusable = config.cpu_quota.is_none();
+            let ret2 = cg_cleanup(cgroup, reusable).context("failed to cleanup cgroup");
+            ret2.and(ret1)?
+        };
+
+        let peak_rss = peak_rss.map(|rss| rss / 1024); // bytes => KiB
+        let peak_total = m.memory / 1024; // bytes => KiB
+        let memory_basis = config.memory_basis.unwrap_or(MemoryBasis::Total);
+
+        Ok(SandboxOutput {
+            code,
+            signal,
+            real_time: real_duration.as_millis() as u64,
+            sys_time: m.sys_time / 1_000_000,   // ns => ms
+            user_time: m.user_time / 1_000_000, // ns => ms
+            memory: match memory_basis {
+                MemoryBasis::Total => peak_total,
+                MemoryBasis::Rss => peak_rss.unwrap_or(0),
+            },
+            swap: m.memsw.saturating_sub(m.memory) / 1024, // bytes => KiB
+            peak_rss,
+            peak_total,
+            memory_basis,
+            max_rss: rusage.ru_maxrss as u64,             // KiB
+            throttled_time: m.throttled_time / 1_000_000, // ns => ms
+            io_read: m.io_read,
+            io_write: m.io_write,
+            samples,
+        })
+    }
+}
+
+/// how long `pause` waits for all processes to be frozen
+const FREEZE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// wait until the state is `FROZEN`, which stays `FREEZING` until all processes are frozen
+fn wait_frozen(freezer: &str) -> Result<()> {
+    let deadline = Instant::now() + FREEZE_TIMEOUT;
+    loop {
+        let state = Cgroup::read_string(freezer, "freezer.state")
+            .context("failed to read freezer state")?;
+        if state.trim_end() == "FROZEN" {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            anyhow::bail!(
+                "timed out waiting for cgroup to freeze: state = {}",
+                state.trim_end()
+            );
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        if let Some(killer) = self.killer.take() {
+            killer.abort();
+        }
+
+        // the sandbox has not been waited
+        if let Some(cgroup) = self.cgroup.take() {
+            let _ = signal::send_signal(self.child_pid, Signal::SIGKILL);
+            if let Some(freezer) = cgroup.freezer() {
+                let _ = Cgroup::write_type(freezer, "freezer.state", "THAWED");
+            }
+            let ret = wait_child(self.child_pid);
+            debug!(child_pid = ?self.child_pid, ?ret, "wait dropped sandbox");
+            if let Err(err) = cg_cleanup(cgroup, false) {
+                warn!(%err, "failed to cleanup cgroup");
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 struct Metrics {
-    sys_time: u64,  // ns
-    user_time: u64, // ns
-    memory: u64,    // bytes
+    sys_time: u64,         // ns
+    user_time: u64,        // ns
+    memory: u64,           // bytes
+    memsw: u64,            // bytes, 0 without swap accounting
+    throttled_time: u64,   // ns
+    io_read: Option<u64>,  // bytes, `None` without the blkio controller
+    io_write: Option<u64>, // bytes, `None` without the blkio controller
 }
 
-fn cg_collect(cg: &Cgroup) -> Result<Metrics> {
+fn cg_collect(config: &SandboxConfig, cg: &Cgroup) -> Result<Metrics> {
     let sys_time = Cgroup::read_type::<u64>(cg.cpu(), "cpuacct.usage_sys")?;
     let user_time = Cgroup::read_type::<u64>(cg.cpu(), "cpuacct.usage_user")?;
     let memory = Cgroup::read_type::<u64>(cg.memory(), "memory.max_usage_in_bytes")?;
+    let memsw = if Cgroup::has_file(cg.memory(), "memory.memsw.max_usage_in_bytes") {
+        Cgroup::read_type::<u64>(cg.memory(), "memory.memsw.max_usage_in_bytes")?
+    } else {
+        0
+    };
+
+    // `cpu.stat` belongs to the cpu controller, which is only required by `cpu_quota`
+    let throttled_time = if config.cpu_quota.is_some() {
+        let content = Cgroup::read_string(cg.cpu(), "cpu.stat")?;
+        let value = content
+            .lines()
+            .find_map(|line| line.strip_prefix("throttled_time "))
+            .context("failed to find throttled_time in cpu.stat")?;
+        value.parse::<u64>()?
+    } else {
+        0
+    };
+
+    // the blkio controller is only joined with io limits
+    let (mut io_read, mut io_write) = (None, None);
+    if let Some(blkio) = cg.blkio() {
+        let (mut read, mut write) = (0, 0);
+        let content = Cgroup::read_string(blkio, "blkio.throttle.io_service_bytes")?;
+        for line in content.lines() {
+            // "<major>:<minor> <op> <bytes>" for each device, and then "Total <bytes>"
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [_, "Read", bytes] => read += bytes.parse::<u64>()?,
+                [_, "Write", bytes] => write += bytes.parse::<u64>()?,
+                _ => {}
+            }
+        }
+        io_read = Some(read);
+        io_write = Some(write);
+    }
 
     let metrics = Metrics {
         sys_time,
         user_time,
         memory,
+        memsw,
+        throttled_time,
+        io_read,
+        io_write,
     };
 
     trace!(?metrics);
@@ -171,7 +651,10 @@ fn cg_collect(cg: &Cgroup) -> Result<Metrics> {
     Ok(metrics)
 }
 
-fn cg_cleanup(cg: Cgroup) -> Result<()> {
+/// kill the remaining processes, and then release the cgroup
+///
+/// The cgroup is not `reusable` if some of its counters can not be reset.
+fn cg_cleanup(cg: Cgroup, reusable: bool) -> Result<()> {
     let content =
         Cgroup::read_string(cg.cpu(), "cgroup.procs").context("failed to read cgroup procs")?;
 
@@ -185,6 +668,12 @@ fn cg_cleanup(cg: Cgroup) -> Result<()> {
         }
         trace!(?pids);
         signal::killall(&pids);
+        // a frozen process can only die after being thawed
+        if let Some(freezer) = cg.freezer() {
+            if let Err(err) = Cgroup::write_type(freezer, "freezer.state", "THAWED") {
+                warn!(%err, "failed to thaw cgroup")
+            }
+        }
         for pid in pids {
             let ret = wait_child(pid);
             debug!(?pid, ?ret, "wait cgroup procs");
@@ -193,15 +682,7 @@ fn cg_cleanup(cg: Cgroup) -> Result<()> {
         trace!("no remaining cgroup procs");
     }
 
-    if let Err(err) = Cgroup::remove_dir(cg.cpu()) {
-        warn!(path = ?cg.cpu(), %err, "failed to remove cgroup dir")
-    }
-    if let Err(err) = Cgroup::remove_dir(cg.memory()) {
-        warn!(path = ?cg.memory(), %err, "failed to remove cgroup dir")
-    }
-    if let Err(err) = Cgroup::remove_dir(cg.pids()) {
-        warn!(path = ?cg.pids(), %err, "failed to remove cgroup dir")
-    }
+    cgroup_pool::release(cg, reusable);
 
     Ok(())
 }
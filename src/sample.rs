@@ -0,0 +1,145 @@
+use crate::cgroup_v1::Cgroup;
+use crate::Sample;
+
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use tokio::task::{self, JoinHandle};
+use tokio::time::{self, MissedTickBehavior};
+use tracing::warn;
+
+/// A task which reads the cgroup counters periodically until it is finished or dropped.
+pub struct Sampler {
+    handle: JoinHandle<()>,
+    samples: Arc<Mutex<Vec<Sample>>>,
+}
+
+impl Sampler {
+    pub fn spawn(cg: &Cgroup, interval_ms: u64, t0: Instant) -> Self {
+        let samples: Arc<Mutex<Vec<Sample>>> = Arc::default();
+
+        let cg = cg.clone();
+        let handle = {
+            let samples = Arc::clone(&samples);
+            task::spawn(async move {
+                let mut interval = time::interval(Duration::from_millis(interval_ms));
+                interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                loop {
+                    interval.tick().await;
+                    match read_sample(&cg, t0) {
+                        Ok(sample) => samples.lock().unwrap().push(sample),
+                        Err(err) => {
+                            warn!(%err, "failed to sample cgroup");
+                            break;
+                        }
+                    }
+                }
+            })
+        };
+
+        Self { handle, samples }
+    }
+
+    pub fn finish(self) -> Vec<Sample> {
+        self.handle.abort();
+        mem::take(&mut *self.samples.lock().unwrap())
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// the polling interval of `PeakTracker`
+const PEAK_INTERVAL_MS: u64 = 10;
+
+/// A task which tracks the peak `total_rss` of the memory cgroup,
+/// since the kernel only records the peak usage including page cache.
+///
+/// The peaks between two polls are missed.
+pub struct PeakTracker {
+    handle: JoinHandle<()>,
+    cg: Cgroup,
+    peak_rss: Arc<AtomicU64>, // bytes
+}
+
+impl PeakTracker {
+    pub fn spawn(cg: &Cgroup) -> Self {
+        let peak_rss: Arc<AtomicU64> = Arc::default();
+
+        let handle = {
+            let cg = cg.clone();
+            let peak_rss = Arc::clone(&peak_rss);
+            task::spawn(async move {
+                let mut interval = time::interval(Duration::from_millis(PEAK_INTERVAL_MS));
+                interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                loop {
+                    interval.tick().await;
+                    if let Err(err) = update_peak(&cg, &peak_rss) {
+                        warn!(%err, "failed to read rss of cgroup");
+                        break;
+                    }
+                }
+            })
+        };
+
+        Self {
+            handle,
+            cg: cg.clone(),
+            peak_rss,
+        }
+    }
+
+    /// read the rss once more, out of the polling interval
+    pub fn update(&self) -> Result<()> {
+        update_peak(&self.cg, &self.peak_rss)
+    }
+
+    /// stop tracking and return the peak rss in bytes
+    pub fn finish(self) -> u64 {
+        self.handle.abort();
+        self.peak_rss.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for PeakTracker {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+fn update_peak(cg: &Cgroup, peak_rss: &AtomicU64) -> Result<()> {
+    let rss = read_rss(cg)?;
+    peak_rss.fetch_max(rss, Ordering::SeqCst);
+    Ok(())
+}
+
+/// read the current `total_rss` of the memory cgroup in bytes
+fn read_rss(cg: &Cgroup) -> Result<u64> {
+    let stat = Cgroup::read_string(cg.memory(), "memory.stat")?;
+    let rss = stat
+        .lines()
+        .find_map(|line| line.strip_prefix("total_rss "))
+        .context("failed to find total_rss in memory.stat")?
+        .parse::<u64>()?;
+    Ok(rss)
+}
+
+pub fn read_sample(cg: &Cgroup, t0: Instant) -> Result<Sample> {
+    let time = t0.elapsed().as_millis() as u64;
+    let cpu_time = Cgroup::read_type::<u64>(cg.cpu(), "cpuacct.usage")?;
+    let usage = Cgroup::read_type::<u64>(cg.memory(), "memory.usage_in_bytes")?;
+    let rss = read_rss(cg)?;
+
+    Ok(Sample {
+        time,
+        cpu_time: cpu_time / 1_000_000, // ns => ms
+        memory: usage / 1024,           // bytes => KiB
+        rss: rss / 1024,                // bytes => KiB
+    })
+}
@@ -1,14 +1,82 @@
-use std::time::Duration;
+use crate::cgroup_v1::Cgroup;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use nix::sys::signal::{self, Signal};
 use nix::unistd::Pid;
+use once_cell::sync::Lazy;
+use tokio::sync::Notify;
 use tokio::task::{self, JoinHandle};
 use tokio::time;
 use tracing::trace;
 
-pub fn async_kill(child_pid: Pid, timeout_ms: u64) -> JoinHandle<()> {
+/// A wall clock which does not advance while the sandbox is frozen
+pub struct ActiveClock {
+    t0: Instant,
+    state: Mutex<FrozenState>,
+    thawed: Notify,
+}
+
+#[derive(Default)]
+struct FrozenState {
+    total: Duration,
+    since: Option<Instant>,
+}
+
+impl ActiveClock {
+    pub fn new(t0: Instant) -> Self {
+        Self {
+            t0,
+            state: Mutex::default(),
+            thawed: Notify::new(),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let frozen = state.total + state.since.map_or(Duration::ZERO, |since| now - since);
+        (now - self.t0).saturating_sub(frozen)
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.state.lock().unwrap().since.is_some()
+    }
+
+    pub fn freeze(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.since.get_or_insert_with(Instant::now);
+    }
+
+    pub fn thaw(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(since) = state.since.take() {
+            state.total += since.elapsed();
+        }
+        self.thawed.notify_waiters();
+    }
+}
+
+/// kill the child when the active time of `clock` reaches the timeout
+pub fn async_kill(child_pid: Pid, timeout_ms: u64, clock: Arc<ActiveClock>) -> JoinHandle<()> {
+    let timeout = Duration::from_millis(timeout_ms);
     task::spawn(async move {
-        time::sleep(Duration::from_millis(timeout_ms)).await;
+        loop {
+            // created before checking the state, so that no wakeup is missed
+            let thawed = clock.thawed.notified();
+            let remaining = timeout.saturating_sub(clock.elapsed());
+            if remaining == Duration::ZERO {
+                break;
+            }
+            if clock.is_frozen() {
+                thawed.await;
+            } else {
+                // the clock may be frozen while sleeping, so check it again
+                time::sleep(remaining).await;
+            }
+        }
         let _ = send_signal(child_pid, Signal::SIGKILL);
     })
 }
@@ -33,3 +101,52 @@ pub fn killall(pids: &[Pid]) {
         let _ = send_signal(pid, Signal::SIGKILL);
     }
 }
+
+/// the child process of a running sandbox, with its freezer cgroup if any
+struct Running {
+    pid: Pid,
+    freezer: Option<String>,
+}
+
+static RUNNING: Lazy<Mutex<Vec<Running>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// whether `kill_all` has been called, which is only changed with `RUNNING` locked
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// A running sandbox which is registered until dropped
+pub struct Registered(Pid);
+
+/// register a child which has just been cloned
+///
+/// The child is killed at once if `kill_all` has been called,
+/// since it may be cloned before `kill_all` but registered after it.
+pub fn register(child_pid: Pid, freezer: Option<&str>) -> Registered {
+    let mut running = RUNNING.lock().unwrap();
+    running.push(Running {
+        pid: child_pid,
+        freezer: freezer.map(ToOwned::to_owned),
+    });
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        let _ = send_signal(child_pid, Signal::SIGKILL);
+    }
+    Registered(child_pid)
+}
+
+impl Drop for Registered {
+    fn drop(&mut self) {
+        RUNNING.lock().unwrap().retain(|r| r.pid != self.0);
+    }
+}
+
+/// kill all running sandboxes
+pub fn kill_all() {
+    let running = RUNNING.lock().unwrap();
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    for r in running.iter() {
+        let _ = send_signal(r.pid, Signal::SIGKILL);
+        // a frozen process can only die after being thawed
+        if let Some(ref freezer) = r.freezer {
+            let _ = Cgroup::write_type(freezer, "freezer.state", "THAWED");
+        }
+    }
+}
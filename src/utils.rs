@@ -1,8 +1,11 @@
 use std::ffi::{CStr, CString};
-use std::mem::MaybeUninit;
-
+use std::mem::{self, MaybeUninit};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
 use std::{io, ptr, slice};
 
+use nix::fcntl::OFlag;
+use nix::sys::stat::Mode;
 use nix::NixPath;
 
 pub type RawFd = std::os::unix::io::RawFd;
@@ -50,3 +53,38 @@ pub fn with_c_str<T>(bytes: &[u8], f: impl FnOnce(&CStr) -> io::Result<T>) -> io
         f(c_str)
     }
 }
+
+pub const RESOLVE_NO_MAGICLINKS: u64 = 0x02;
+pub const RESOLVE_NO_SYMLINKS: u64 = 0x04;
+pub const RESOLVE_IN_ROOT: u64 = 0x10;
+
+/// https://man7.org/linux/man-pages/man2/openat2.2.html
+pub fn openat2(
+    dirfd: RawFd,
+    path: &Path,
+    flags: OFlag,
+    mode: Mode,
+    resolve: u64,
+) -> io::Result<RawFd> {
+    #[repr(C)]
+    struct OpenHow {
+        flags: u64,
+        mode: u64,
+        resolve: u64,
+    }
+
+    let how = OpenHow {
+        flags: flags.bits() as u64,
+        mode: mode.bits() as u64,
+        resolve,
+    };
+
+    with_c_str(path.as_os_str().as_bytes(), |path| {
+        let ret = libc_call(|| unsafe {
+            let how: *const OpenHow = &how;
+            let size = mem::size_of::<OpenHow>();
+            libc::syscall(libc::SYS_openat2, dirfd, path.as_ptr(), how, size) as _
+        })?;
+        Ok(ret as RawFd)
+    })
+}
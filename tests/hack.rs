@@ -1,6 +1,9 @@
-use carapace::{SandboxConfig, SandboxOutput};
+use carapace::{BindMount, Namespace, SandboxConfig, SandboxOutput};
 
 use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
 use std::sync::Once;
 
 use anyhow::Result;
@@ -234,3 +237,225 @@ async fn t06_zombie() -> Result<()> {
         assert_le!(output.user_time, 100);
     })
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn t07_mount_dev() -> Result<()> {
+    init();
+
+    let args = &SandboxConfig {
+        bin: "/bin/sh".into(),
+        args: vec![
+            "-c".into(),
+            "test -c /dev/null && test -c /dev/urandom && test ! -e /dev/mem".into(),
+        ],
+        mount_dev: true,
+        real_time_limit: Some(1000),
+        ..Default::default()
+    };
+
+    let output = run(args)?;
+    assert_eq!(output.code, 0);
+    assert_eq!(output.signal, 0);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn t08_pause() -> Result<()> {
+    init();
+
+    let args = &SandboxConfig {
+        bin: "/bin/sleep".into(),
+        args: vec!["0.5".into()],
+        real_time_limit: Some(1000),
+        ..Default::default()
+    };
+
+    let sandbox = carapace::spawn(args)?;
+    sandbox.pause()?;
+    std::thread::sleep(std::time::Duration::from_millis(1000));
+    sandbox.resume()?;
+    let output = sandbox.wait()?;
+
+    // the frozen time is counted by neither `real_time` nor the real time limit
+    assert_eq!(output.code, 0);
+    assert_eq!(output.signal, 0);
+    assert_le!(output.real_time, 1000);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn t09_cgroup_pool() -> Result<()> {
+    init();
+
+    // the other tests run in parallel, so the pool has room for their cgroups as well
+    carapace::set_cgroup_pool_size(64);
+    let _reset_pool = scopeguard::guard((), |_| carapace::set_cgroup_pool_size(0));
+
+    // NOTE:
+    // No other test uses a cpuset, so the pooled cgroup can only be taken by this test.
+    // The cgroups are printed by shell builtins, and then two commands must fork.
+    let script =
+        "while read -r line; do echo \"$line\"; done </proc/self/cgroup; /bin/true; /bin/true";
+    let limited = &SandboxConfig {
+        bin: "/bin/sh".into(),
+        args: vec!["-c".into(), script.into()],
+        cpus: Some("0".into()),
+        cg_limit_max_pids: Some(1),
+        stdout: Some(tmp!("t09_stdout_1").into()),
+        real_time_limit: Some(1000),
+        ..Default::default()
+    };
+    let output = run(limited)?;
+    assert_ne!(output.code, 0);
+
+    // the limit of the reused cgroup has been reset
+    let unlimited = &SandboxConfig {
+        cg_limit_max_pids: None,
+        stdout: Some(tmp!("t09_stdout_2").into()),
+        ..limited.clone()
+    };
+    let output = run(unlimited)?;
+    assert_eq!(output.code, 0);
+    assert_eq!(output.signal, 0);
+
+    let cpuset_of = |path: &str| -> Result<String> {
+        let content = fs::read_to_string(path)?;
+        let line = content.lines().find(|l| l.contains(":cpuset:"));
+        Ok(line.unwrap_or_default().to_owned())
+    };
+    let cgroup = cpuset_of(tmp!("t09_stdout_1"))?;
+    assert!(cgroup.contains("carapace_"), "{}", cgroup);
+    assert_eq!(cgroup, cpuset_of(tmp!("t09_stdout_2"))?);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn t10_symlink_escape() -> Result<()> {
+    init();
+
+    let root = tmp!("t10_root");
+    fs::create_dir_all(root)?;
+    let _ = fs::remove_file(concat!(tmp!("t10_root"), "/tmp"));
+    std::os::unix::fs::symlink("/etc", concat!(tmp!("t10_root"), "/tmp"))?;
+
+    let args = &SandboxConfig {
+        bin: "/bin/true".into(),
+        chroot: Some(root.into()),
+        bindmount_ro: vec![BindMount {
+            src: "/bin".into(),
+            dst: "/tmp/carapace_t10".into(),
+        }],
+        ..Default::default()
+    };
+
+    // the symlink is rejected instead of being followed out of the root
+    assert!(run(args).is_err());
+    assert!(!Path::new("/etc/carapace_t10").exists());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn t11_write_only_stdin() -> Result<()> {
+    init();
+
+    let file = fs::File::create(tmp!("t11_stdin"))?;
+
+    let args = &SandboxConfig {
+        bin: "/bin/true".into(),
+        stdin_fd: Some(file.as_raw_fd()),
+        ..Default::default()
+    };
+
+    let err = run(args).unwrap_err();
+    assert!(err.to_string().contains("not readable"), "{:?}", err);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn t12_capabilities() -> Result<()> {
+    init();
+
+    let stdout = tmp!("t12_stdout");
+
+    let args = &SandboxConfig {
+        bin: "/bin/grep".into(),
+        args: vec!["^Cap".into(), "/proc/self/status".into()],
+        stdout: Some(stdout.into()),
+        real_time_limit: Some(1000),
+        ..Default::default()
+    };
+
+    let output = run(args)?;
+    assert_eq!(output.code, 0);
+
+    let status = fs::read_to_string(stdout)?;
+    for field in &["CapInh", "CapPrm", "CapEff", "CapBnd", "CapAmb"] {
+        let line = status.lines().find(|l| l.starts_with(field)).unwrap();
+        assert!(line.ends_with("0000000000000000"), "{}", line);
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn t13_time_namespace() -> Result<()> {
+    init();
+
+    let args = &SandboxConfig {
+        bin: "/bin/true".into(),
+        namespaces: Some(vec![Namespace::Pid, Namespace::Time]),
+        real_time_limit: Some(1000),
+        ..Default::default()
+    };
+
+    let output = run(args)?;
+    assert_eq!(output.code, 0);
+
+    // the later children of the calling thread stay in its time namespace
+    let ns = fs::read_link("/proc/thread-self/ns/time")?;
+    let ns_for_children = fs::read_link("/proc/thread-self/ns/time_for_children")?;
+    assert_eq!(ns, ns_for_children);
+    Ok(())
+}
+
+#[test]
+fn t14_no_runtime() -> Result<()> {
+    init();
+
+    // a tokio runtime is only required by the real time limit and the rss tracking
+    let args = &SandboxConfig {
+        bin: "/bin/true".into(),
+        ..Default::default()
+    };
+
+    let output = run(args)?;
+    assert_eq!(output.code, 0);
+    assert!(output.peak_rss.is_none());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn t15_search_path_as_user() -> Result<()> {
+    init();
+
+    let dir = tmp!("t15_bin");
+    fs::create_dir_all(dir)?;
+    let bin = Path::new(dir).join("prog");
+    fs::write(&bin, "#!/bin/sh\n")?;
+
+    // only the owner (root) can execute it
+    fs::set_permissions(&bin, fs::Permissions::from_mode(0o700))?;
+
+    let args = &SandboxConfig {
+        bin: "prog".into(),
+        search_path: true,
+        env: vec![format!("PATH={}", dir).into()],
+        uid: Some(1000),
+        gid: Some(1000),
+        real_time_limit: Some(1000),
+        ..Default::default()
+    };
+
+    let err = run(args).unwrap_err();
+    assert!(format!("{:?}", err).contains("not executable"), "{:?}", err);
+    Ok(())
+}